@@ -0,0 +1,147 @@
+# Duplicate type units are compared with the type unit in the DWARF package with
+# `--compare-type-units`, after reading strings from the string tables of each input.
+
+# RUN: llvm-mc --triple=x86_64-unknown-linux --filetype=obj --split-dwarf-file=%t-a.dwo \
+# RUN:   -dwarf-version=5 --defsym VARIANT=1 %s -o %t-a.o
+# RUN: llvm-mc --triple=x86_64-unknown-linux --filetype=obj --split-dwarf-file=%t-b.dwo \
+# RUN:   -dwarf-version=5 --defsym VARIANT=2 %s -o %t-b.o
+# RUN: llvm-mc --triple=x86_64-unknown-linux --filetype=obj --split-dwarf-file=%t-c.dwo \
+# RUN:   -dwarf-version=5 --defsym VARIANT=3 %s -o %t-c.o
+
+# RUN: thorin --compare-type-units %t-a.dwo %t-b.dwo %t-c.dwo -o %t.dwp 2>&1 | FileCheck %s
+# RUN: thorin %t-a.dwo %t-b.dwo %t-c.dwo -o %t.dwp 2>&1 | count 0
+# RUN: thorin --compare-type-units %t-a.dwo %t-b.dwo -o %t.dwp 2>&1 | count 0
+# RUN: thorin %t-b.dwo -o %t-b.dwp
+# RUN: thorin --compare-type-units %t-a.dwo %t-b.dwp -o %t.dwp 2>&1 | count 0
+# RUN: thorin --compare-type-units %p/inputs/type-dedup-a.dwo %p/inputs/type-dedup-b.dwo \
+# RUN:   -o %t.dwp 2>&1 | count 0
+
+# CHECK: warning: Type unit 0x0000000000001234 in `{{.*}}-c.dwo`, section `.debug_info.dwo` at offset 0x19 differs from the type unit in the DWARF package from `{{.*}}-a.dwo`, section `.debug_info.dwo` at offset 0x19
+# CHECK-NOT: warning
+
+	.section	.debug_info.dwo,"e",@progbits
+.Ltu_begin:
+	.long	.Ltu_end-.Ltu_start    # Length of Unit
+.Ltu_start:
+	.short	5                       # DWARF version number
+	.byte	6                       # DWARF Unit Type (DW_UT_split_type)
+	.byte	8                       # Address Size (in bytes)
+	.long	0                       # Offset Into Abbrev. Section
+	.quad	0x1234                  # Type Signature
+	.long	.Lstruct-.Ltu_begin     # Type DIE Offset
+	.byte	1                       # Abbrev [1] DW_TAG_type_unit
+.Lstruct:
+	.byte	2                       # Abbrev [2] DW_TAG_structure_type
+	.byte	.Lname_idx              # DW_AT_name
+	.byte	4                       # DW_AT_byte_size
+	.byte	3                       # Abbrev [3] DW_TAG_member
+	.byte	.Lx_idx                 # DW_AT_name
+	.long	.Lint-.Ltu_begin        # DW_AT_type
+	.byte	0                       # DW_AT_data_member_location
+	.byte	0                       # End Of Children Mark
+.Lint:
+	.byte	4                       # Abbrev [4] DW_TAG_base_type
+	.byte	.Lint_idx               # DW_AT_name
+	.byte	5                       # DW_AT_encoding (DW_ATE_signed)
+	.byte	4                       # DW_AT_byte_size
+	.byte	0                       # End Of Children Mark
+.Ltu_end:
+
+	.long	.Lcu_end-.Lcu_start    # Length of Unit
+.Lcu_start:
+	.short	5                       # DWARF version number
+	.byte	5                       # DWARF Unit Type (DW_UT_split_compile)
+	.byte	8                       # Address Size (in bytes)
+	.long	0                       # Offset Into Abbrev. Section
+	.quad	VARIANT                 # DWO ID
+	.byte	5                       # Abbrev [5] DW_TAG_compile_unit
+	.byte	0                       # DW_AT_name
+.Lcu_end:
+
+	.section	.debug_abbrev.dwo,"e",@progbits
+	.byte	1                       # Abbreviation Code
+	.byte	65                      # DW_TAG_type_unit
+	.byte	1                       # DW_CHILDREN_yes
+	.byte	0                       # EOM(1)
+	.byte	0                       # EOM(2)
+	.byte	2                       # Abbreviation Code
+	.byte	19                      # DW_TAG_structure_type
+	.byte	1                       # DW_CHILDREN_yes
+	.byte	3                       # DW_AT_name
+	.byte	37                      # DW_FORM_strx1
+	.byte	11                      # DW_AT_byte_size
+	.byte	11                      # DW_FORM_data1
+	.byte	0                       # EOM(1)
+	.byte	0                       # EOM(2)
+	.byte	3                       # Abbreviation Code
+	.byte	13                      # DW_TAG_member
+	.byte	0                       # DW_CHILDREN_no
+	.byte	3                       # DW_AT_name
+	.byte	37                      # DW_FORM_strx1
+	.byte	73                      # DW_AT_type
+	.byte	19                      # DW_FORM_ref4
+	.byte	56                      # DW_AT_data_member_location
+	.byte	11                      # DW_FORM_data1
+	.byte	0                       # EOM(1)
+	.byte	0                       # EOM(2)
+	.byte	4                       # Abbreviation Code
+	.byte	36                      # DW_TAG_base_type
+	.byte	0                       # DW_CHILDREN_no
+	.byte	3                       # DW_AT_name
+	.byte	37                      # DW_FORM_strx1
+	.byte	62                      # DW_AT_encoding
+	.byte	11                      # DW_FORM_data1
+	.byte	11                      # DW_AT_byte_size
+	.byte	11                      # DW_FORM_data1
+	.byte	0                       # EOM(1)
+	.byte	0                       # EOM(2)
+	.byte	5                       # Abbreviation Code
+	.byte	17                      # DW_TAG_compile_unit
+	.byte	0                       # DW_CHILDREN_no
+	.byte	3                       # DW_AT_name
+	.byte	37                      # DW_FORM_strx1
+	.byte	0                       # EOM(1)
+	.byte	0                       # EOM(2)
+	.byte	0                       # EOM(3)
+
+# The second input has an extra string before the strings of the type unit, so its type unit
+# refers to them with different indexes, and the third input's structure has a different name.
+	.section	.debug_str.dwo,"eMS",@progbits,1
+.Lstr_begin:
+.Lcu_name:
+	.asciz	"a.cpp"
+.if VARIANT == 2
+.Lextra:
+	.asciz	"extra"
+.endif
+.Lname:
+.if VARIANT == 3
+	.asciz	"bar"
+.else
+	.asciz	"foo"
+.endif
+.Lx:
+	.asciz	"x"
+.Lint_name:
+	.asciz	"int"
+
+	.section	.debug_str_offsets.dwo,"e",@progbits
+	.long	.Lstr_offsets_end-.Lstr_offsets_start # Length of String Offsets Set
+.Lstr_offsets_start:
+	.short	5
+	.short	0
+	.long	.Lcu_name-.Lstr_begin
+.if VARIANT == 2
+	.long	.Lextra-.Lstr_begin
+.Lname_idx = 2
+.Lx_idx = 3
+.Lint_idx = 4
+.else
+.Lname_idx = 1
+.Lx_idx = 2
+.Lint_idx = 3
+.endif
+	.long	.Lname-.Lstr_begin
+	.long	.Lx-.Lstr_begin
+	.long	.Lint_name-.Lstr_begin
+.Lstr_offsets_end:
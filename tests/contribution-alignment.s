@@ -0,0 +1,96 @@
+# Contributions are aligned to the alignment of their input sections, and index contributions
+# don't include the padding before them.
+
+# RUN: llvm-mc --triple=x86_64-unknown-linux --filetype=obj --split-dwarf-file=%t-a.dwo \
+# RUN:   -dwarf-version=5 --defsym DWOID=1 %s -o %t-a.o
+# RUN: llvm-mc --triple=x86_64-unknown-linux --filetype=obj --split-dwarf-file=%t-b.dwo \
+# RUN:   -dwarf-version=5 --defsym DWOID=2 %s -o %t-b.o
+
+# RUN: thorin %t-a.dwo %t-b.dwo -o %t.dwp
+# RUN: llvm-readelf -S %t.dwp | FileCheck --check-prefix=SECTIONS %s
+# RUN: llvm-dwarfdump -debug-cu-index %t.dwp | FileCheck %s
+
+# RUN: rm -rf %t-tmp && mkdir -p %t-tmp
+# RUN: thorin %t-a.dwo %t-b.dwo --streaming-output %t-tmp -o %t-streaming.dwp
+# RUN: cmp %t.dwp %t-streaming.dwp
+
+# SECTIONS: .debug_abbrev.dwo {{.*}} 00 0 0 4
+# SECTIONS: .debug_info.dwo {{.*}} 00 0 0 1
+# SECTIONS: .debug_cu_index {{.*}} 00 0 0 8
+
+# CHECK: Index Signature          INFO                     ABBREV
+# CHECK-DAG: 0x0000000000000001 [0x00000000, 0x00000054) [0x00000000, 0x0000002a)
+# CHECK-DAG: 0x0000000000000002 [0x00000054, 0x000000a8) [0x0000002c, 0x00000056)
+
+	.section	.debug_info.dwo,"e",@progbits
+	.long	.Ldebug_info_dwo_end0-.Ldebug_info_dwo_start0 # Length of Unit
+.Ldebug_info_dwo_start0:
+	.short	5                      # DWARF version number
+	.byte	5                       # DWARF Unit Type
+	.byte	8                       # Address Size (in bytes)
+	.long	0                       # Offset Into Abbrev. Section
+	.quad	DWOID
+	.byte	1                       # Abbrev [1] 0x14:0x16 DW_TAG_compile_unit
+	.asciz  "clang version 11.0.0" # DW_AT_producer
+	.short	12                     # DW_AT_language
+	.asciz  "int.c"                # DW_AT_name
+	.asciz  "int.dwo"              # DW_AT_dwo_name
+	.byte	2                       # Abbrev [2] 0x1a:0xb DW_TAG_variable
+	.asciz  "integer"              # DW_AT_name
+	.long	37                      # DW_AT_type
+                                        # DW_AT_external
+	.byte	0                       # DW_AT_decl_file
+	.byte	1                       # DW_AT_decl_line
+	.byte	2                       # DW_AT_location
+	.byte	161
+	.byte	0
+	.byte	3                       # Abbrev [3] 0x25:0x4 DW_TAG_base_type
+	.asciz  "int"                  # DW_AT_name
+	.byte	5                       # DW_AT_encoding
+	.byte	4                       # DW_AT_byte_size
+	.byte	0                       # End Of Children Mark
+.Ldebug_info_dwo_end0:
+	.section	.debug_abbrev.dwo,"e",@progbits
+	.p2align	2
+	.byte	1                       # Abbreviation Code
+	.byte	17                      # DW_TAG_compile_unit
+	.byte	1                       # DW_CHILDREN_yes
+	.byte	37                      # DW_AT_producer
+	.byte	8                       # DW_FORM_string
+	.byte	19                      # DW_AT_language
+	.byte	5                       # DW_FORM_data2
+	.byte	3                       # DW_AT_name
+	.byte	8                       # DW_FORM_string
+	.byte	118                     # DW_AT_dwo_name
+	.byte	8                       # DW_FORM_string
+	.byte	0                       # EOM(1)
+	.byte	0                       # EOM(2)
+	.byte	2                       # Abbreviation Code
+	.byte	52                      # DW_TAG_variable
+	.byte	0                       # DW_CHILDREN_no
+	.byte	3                       # DW_AT_name
+	.byte	8                       # DW_FORM_string
+	.byte	73                      # DW_AT_type
+	.byte	19                      # DW_FORM_ref4
+	.byte	63                      # DW_AT_external
+	.byte	25                      # DW_FORM_flag_present
+	.byte	58                      # DW_AT_decl_file
+	.byte	11                      # DW_FORM_data1
+	.byte	59                      # DW_AT_decl_line
+	.byte	11                      # DW_FORM_data1
+	.byte	2                       # DW_AT_location
+	.byte	24                      # DW_FORM_exprloc
+	.byte	0                       # EOM(1)
+	.byte	0                       # EOM(2)
+	.byte	3                       # Abbreviation Code
+	.byte	36                      # DW_TAG_base_type
+	.byte	0                       # DW_CHILDREN_no
+	.byte	3                       # DW_AT_name
+	.byte	8                       # DW_FORM_string
+	.byte	62                      # DW_AT_encoding
+	.byte	11                      # DW_FORM_data1
+	.byte	11                      # DW_AT_byte_size
+	.byte	11                      # DW_FORM_data1
+	.byte	0                       # EOM(1)
+	.byte	0                       # EOM(2)
+	.byte	0                       # EOM(3)
@@ -0,0 +1,61 @@
+# RUN: llvm-mc -triple x86_64-unknown-linux %s -filetype=obj -o %t.dwp
+# RUN: not thorin inspect %t.dwp 2>&1 | FileCheck %s
+
+# Checks that an index with more than one row for the same unit is reported as an error, rather
+# than only the last of the rows being read.
+
+# CHECK: Error: Failed to read DWARF package `{{.*}}/duplicate-index-entry.s.tmp.dwp`
+# CHECK: Error in `{{.*}}/duplicate-index-entry.s.tmp.dwp`, section `.debug_cu_index`
+# CHECK: Index has more than one entry for unit 0x1100001122222222
+
+    .section .debug_info.dwo, "e", @progbits
+    .long	.Ldebug_info_dwo_end0-.Ldebug_info_dwo_start0 # Length of Unit
+.Ldebug_info_dwo_start0:
+    .short 5                       # DWARF version number
+    .byte 5                        # DWARF Unit type (DW_UT_split_compile)
+    .byte 8                        # Address Size (in bytes)
+    .long 0                        # Offset Into Abbrev. Section
+    .quad 0x1100001122222222
+    .byte 1                        # Abbrev [1] DW_TAG_compile_unit
+.Ldebug_info_dwo_end0:
+    .section .debug_abbrev.dwo, "e", @progbits
+    .byte 1                        # Abbreviation Code
+    .byte 17                       # DW_TAG_compile_unit
+    .byte 0                        # DW_CHILDREN_no
+    .byte 0                        # EOM(1)
+    .byte 0                        # EOM(2)
+    .byte 0                        # EOM(3)
+    .section .debug_cu_index, "", @progbits
+## Header:
+    .short 5                        # Version
+    .space 2                        # Padding
+    .long 2                         # Section count
+    .long 2                         # Unit count
+    .long 4                         # Slot count
+## Hash Table of Signatures:
+    .quad 0
+    .quad 0x1100001122222222
+    .quad 0x1100001122222222
+    .quad 0
+## Parallel Table of Indexes:
+    .long 0
+    .long 1
+    .long 2
+    .long 0
+## Table of Section Offsets:
+## Row 0:
+    .long 1                         # DW_SECT_INFO
+    .long 3                         # DW_SECT_ABBREV
+## Row 1:
+    .long 0
+    .long 0
+## Row 2:
+    .long 0
+    .long 0
+## Table of Section Sizes:
+## Row 1:
+    .long .Ldebug_info_dwo_end0-.Ldebug_info_dwo_start0+4
+    .long 6
+## Row 2:
+    .long .Ldebug_info_dwo_end0-.Ldebug_info_dwo_start0+4
+    .long 6
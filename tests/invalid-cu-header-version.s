@@ -3,8 +3,9 @@
 # RUN: not thorin %t.dwo -o %t.dwp 2>&1 | FileCheck %s
 
 # CHECK: Error: Failed to add `{{.*}}/invalid-cu-header-version.s.tmp.dwo` to DWARF package
-# CHECK:  0: Failed to parse unit header
-# CHECK:  1: Hit the end of input before it was expected
+# CHECK:  0: Error in `{{.*}}`, section `.debug_info.dwo` at offset 0x0
+# CHECK:  1: Failed to parse unit header
+# CHECK:  2: Hit the end of input before it was expected
 
     .section	.debug_info.dwo,"e",@progbits
     .long	0 # Length of Unit
@@ -1,5 +1,6 @@
 import lit
 import os
+import subprocess
 import tempfile
 
 config.name = "thorin"
@@ -8,3 +9,16 @@ config.excludes = ['inputs']
 config.suffixes = ['.s', '.test']
 config.test_source_root = os.path.dirname(__file__)
 config.test_exec_root = tempfile.TemporaryDirectory().name
+
+# Optional features of `thorin` are detected by whether it accepts the options they add, so that
+# tests of those features can be marked with `REQUIRES`.
+thorin = lit.util.which('thorin', config.environment['PATH'])
+if thorin is not None:
+    def accepts(*args):
+        return subprocess.run([thorin, *args], stdout=subprocess.DEVNULL,
+                              stderr=subprocess.DEVNULL).returncode == 0
+
+    if accepts('--jobs', '1'):
+        config.available_features.add('rayon')
+    if accepts('--compress-debug-sections', 'zstd'):
+        config.available_features.add('zstd')
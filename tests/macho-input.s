@@ -0,0 +1,109 @@
+# RUN: llvm-mc --triple=x86_64-apple-macosx --filetype=obj %s -o %t.dwo
+# RUN: thorin --output-format elf %t.dwo -o %t.dwp
+# RUN: llvm-dwarfdump -v %t.dwp | FileCheck %s
+# RUN: rm -f %t.a
+# RUN: llvm-ar q %t.a %t.dwo
+# RUN: thorin --output-format elf %t.a -o - | llvm-dwarfdump -v - | FileCheck %s
+
+# Without an explicit output format, the format of the input is used.
+# RUN: thorin %t.dwo -o - | llvm-objdump -h - | FileCheck --check-prefix=MACHO %s
+
+# Mach-O section names are limited to sixteen bytes, so most DWARF object sections have truncated
+# names (e.g. `__debug_abbrev.d` and `__debug_str_offs`).
+
+# Only sections in the `__DWARF` segment are DWARF object sections.
+# RUN: llvm-mc --triple=x86_64-apple-macosx --filetype=obj --defsym OTHER_SEGMENT=1 %s \
+# RUN:   -o %t-other-segment.dwo
+# RUN: thorin --output-format elf %t-other-segment.dwo -o - | llvm-dwarfdump -v - | FileCheck %s
+
+# `__debug_str_offs`, `__debug_loclists` and `__debug_rnglists` are also the truncated names of
+# non-split DWARF sections, so they are an error in objects which also have non-split DWARF.
+# RUN: llvm-mc --triple=x86_64-apple-macosx --filetype=obj --defsym SKELETON=1 %s \
+# RUN:   -o %t-skeleton.dwo
+# RUN: not thorin %t-skeleton.dwo -o - 2>&1 | FileCheck --check-prefix=AMBIGUOUS %s
+
+# CHECK-LABEL: .debug_abbrev.dwo contents:
+# CHECK: DW_TAG_compile_unit
+
+# CHECK-LABEL: .debug_info.dwo contents:
+# CHECK: 0x00000000: Compile Unit: length = 0x00000016, format = DWARF32, version = 0x0005, unit_type = DW_UT_split_compile, abbr_offset = 0x0000, addr_size = 0x08, DWO_id = [[DWOID:.*]] (next unit at 0x0000001a)
+# CHECK: DW_AT_producer [DW_FORM_strx1] (indexed (00000000) string = "clang version 14.0.0")
+# CHECK: DW_AT_name [DW_FORM_strx1] (indexed (00000001) string = "macho.c")
+# CHECK: DW_AT_dwo_name [DW_FORM_strx1] (indexed (00000002) string = "macho.dwo")
+
+# CHECK-LABEL: .debug_cu_index contents:
+# CHECK: version = 5, units = 1, slots = 2
+# CHECK: Index Signature          INFO                     ABBREV                   STR_OFFSETS
+# CHECK: 1 [[DWOID]] [0x00000000, 0x0000001a) [0x00000000, 0x0000000e) [0x00000000, 0x00000014)
+
+# CHECK-LABEL: .debug_str.dwo contents:
+# CHECK: "clang version 14.0.0"
+# CHECK: "macho.c"
+# CHECK: "macho.dwo"
+
+# MACHO: file format mach-o 64-bit x86-64
+# MACHO: __debug_str_offs
+# MACHO: __debug_abbrev.d
+# MACHO: __debug_info.dwo
+# MACHO: __debug_str.dwo
+# MACHO: __debug_cu_index
+
+# AMBIGUOUS: Error in `{{.*}}skeleton.dwo`, section `__debug_str_offs`
+# AMBIGUOUS-NEXT: Mach-O section could be a DWARF object section or a non-split DWARF section
+
+.ifdef OTHER_SEGMENT
+	.section	__DATA,__debug_info.dwo
+	.long	0xffffffff
+	.section	__DATA,__debug_abbrev.d
+	.byte	0
+.endif
+
+	.section	__DWARF,__debug_str_offs,regular,debug
+	.long	16                              # Length of String Offsets Set
+	.short	5
+	.short	0
+	.long	Lproducer-Ldebug_str
+	.long	Lname-Ldebug_str
+	.long	Ldwo_name-Ldebug_str
+	.section	__DWARF,__debug_str.dwo,regular,debug
+Ldebug_str:
+Lproducer:
+	.asciz	"clang version 14.0.0"
+Lname:
+	.asciz	"macho.c"
+Ldwo_name:
+	.asciz	"macho.dwo"
+	.section	__DWARF,__debug_info.dwo,regular,debug
+	.long	Ldebug_info_dwo_end0-Ldebug_info_dwo_start0 # Length of Unit
+Ldebug_info_dwo_start0:
+	.short	5                               # DWARF version number
+	.byte	5                               # DWARF Unit Type (DW_UT_split_compile)
+	.byte	8                               # Address Size (in bytes)
+	.long	0                               # Offset Into Abbrev. Section
+	.quad	-1173350285159172090
+	.byte	1                               # Abbrev [1] DW_TAG_compile_unit
+	.byte	0                               # DW_AT_producer
+	.short	12                              # DW_AT_language
+	.byte	1                               # DW_AT_name
+	.byte	2                               # DW_AT_dwo_name
+Ldebug_info_dwo_end0:
+	.section	__DWARF,__debug_abbrev.d,regular,debug
+	.byte	1                               # Abbreviation Code
+	.byte	17                              # DW_TAG_compile_unit
+	.byte	0                               # DW_CHILDREN_no
+	.byte	37                              # DW_AT_producer
+	.byte	37                              # DW_FORM_strx1
+	.byte	19                              # DW_AT_language
+	.byte	5                               # DW_FORM_data2
+	.byte	3                               # DW_AT_name
+	.byte	37                              # DW_FORM_strx1
+	.byte	118                             # DW_AT_dwo_name
+	.byte	37                              # DW_FORM_strx1
+	.byte	0                               # EOM(1)
+	.byte	0                               # EOM(2)
+	.byte	0                               # EOM(3)
+
+.ifdef SKELETON
+	.section	__DWARF,__debug_info,regular,debug
+	.byte	0
+.endif
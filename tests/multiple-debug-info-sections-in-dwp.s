@@ -2,8 +2,9 @@
 # RUN: not thorin %t.dwp -o /dev/null 2>&1 | FileCheck %s
 
 # CHECK: Error: Failed to add `{{.*}}/multiple-debug-info-sections-in-dwp.s.tmp.dwp` to DWARF package
-# CHECK:  0: Failed to parse `.debug_cu_index` index section
-# CHECK:  1: Hit the end of input before it was expected
+# CHECK:  0: Error in `{{.*}}`, section `.debug_cu_index`
+# CHECK:  1: Failed to parse `.debug_cu_index` index section
+# CHECK:  2: Hit the end of input before it was expected
 
     .section	.debug_info.dwo,"G",@progbits,0xFDFDFDFD,comdat
     .long	.Ldebug_info_dwo_end1-.Ldebug_info_dwo_start1 # Length of Unit
@@ -3,8 +3,9 @@
 # RUN: not thorin %t.dwo -o /dev/null 2>&1 | FileCheck %s
 
 # CHECK: Error: Failed to add `{{.*}}/no-cu-found.s.tmp.dwo` to DWARF package
-# CHECK:  0: Failed to parse unit header
-# CHECK:  1: The `DW_UT_*` value for this unit is not supported yet
+# CHECK:  0: Error in `{{.*}}`, section `.debug_info.dwo` at offset 0x0
+# CHECK:  1: Failed to parse unit header
+# CHECK:  2: The `DW_UT_*` value for this unit is not supported yet
 
 	.section	.debug_info.dwo,"e",@progbits
 	.long	.Ldebug_info_dwo_end0-.Ldebug_info_dwo_start0 # Length of Unit
@@ -0,0 +1,65 @@
+# RUN: llvm-mc --triple=x86_64 --filetype=obj %s -o %t.o
+# RUN: llvm-readobj -r %t.o | FileCheck --check-prefix=RELOCS %s
+# RUN: thorin -e %t.o --search-dir %p/inputs -o %t.dwp
+# RUN: llvm-dwarfdump -debug-info %t.dwp | FileCheck %s
+
+# PC-relative relocations which target a different section can't be resolved in a relocatable
+# object, as the sections haven't been placed yet.
+# RUN: llvm-mc --triple=x86_64 --filetype=obj --defsym CROSS_SECTION=1 %s -o %t.cross.o
+# RUN: not thorin -e %t.cross.o --search-dir %p/inputs -o %t.cross.dwp 2>&1 \
+# RUN:   | FileCheck --check-prefix=CROSS %s
+
+# Assemblers usually resolve PC-relative differences within a section themselves, but aren't
+# required to (e.g. for references to preemptible symbols), so relocations are emitted explicitly.
+# The unit length is only correct once its PC-relative relocation is applied.
+
+# RELOCS-LABEL: .rela.debug_info {
+# RELOCS-NEXT: 0x0 R_X86_64_PC32 .debug_info 0x18
+# RELOCS-NEXT: 0x6 R_X86_64_32 .debug_abbrev 0x0
+# RELOCS-NEXT: 0xC R_X86_64_32 .debug_str 0x8
+# RELOCS-NEXT: 0x18 R_X86_64_32 .debug_str 0x0
+
+# CHECK-LABEL: .debug_info.dwo contents:
+# CHECK: DW_AT_name ("a.cpp")
+
+# CROSS: Unsupported relocation for section .debug_info at offset 0x00000010
+
+	.text
+.Ltext:
+	nop
+
+	.section	.debug_str,"MS",@progbits,1
+.Lcomp_dir:
+	.asciz	"/absent"
+.Ldwo_name:
+	.asciz	"dwos-list-from-exec-a.dwo"
+
+	.section	.debug_abbrev,"",@progbits
+.Ldebug_abbrev:
+	.byte	1                               # Abbreviation Code
+	.byte	17                              # DW_TAG_compile_unit
+	.byte	0                               # DW_CHILDREN_no
+	.ascii	"\260B"                         # DW_AT_GNU_dwo_name
+	.byte	14                              # DW_FORM_strp
+	.ascii	"\261B"                         # DW_AT_GNU_dwo_id
+	.byte	7                               # DW_FORM_data8
+	.byte	27                              # DW_AT_comp_dir
+	.byte	14                              # DW_FORM_strp
+	.byte	0                               # EOM(1)
+	.byte	0                               # EOM(2)
+	.byte	0                               # EOM(3)
+
+	.section	.debug_info,"",@progbits
+	.reloc	., R_X86_64_PC32, .Ldebug_info_end - 4
+	.long	0                               # Length of Unit
+	.short	4                               # DWARF version number
+	.long	.Ldebug_abbrev                  # Offset Into Abbrev. Section
+	.byte	8                               # Address Size (in bytes)
+	.byte	1                               # Abbrev [1] DW_TAG_compile_unit
+	.long	.Ldwo_name                      # DW_AT_GNU_dwo_name
+.ifdef CROSS_SECTION
+	.reloc	., R_X86_64_PC32, .Ltext
+.endif
+	.quad	0x701370f52cca410e              # DW_AT_GNU_dwo_id
+	.long	.Lcomp_dir                      # DW_AT_comp_dir
+.Ldebug_info_end:
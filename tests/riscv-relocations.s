@@ -0,0 +1,77 @@
+# RUN: llvm-mc --triple=riscv64 --mattr=+relax --filetype=obj %s -o %t.o
+# RUN: llvm-readobj -r %t.o | FileCheck --check-prefix=RELOCS %s
+# RUN: thorin -e %t.o --search-dir %p/inputs -o %t.dwp
+# RUN: llvm-dwarfdump -debug-info %t.dwp | FileCheck %s
+
+# Relocatable objects with skeleton units can also be provided in a static library.
+# RUN: rm -f %t.a
+# RUN: llvm-ar q %t.a %t.o
+# RUN: thorin -e %t.a --search-dir %p/inputs -o %t.archive.dwp
+# RUN: cmp %t.dwp %t.archive.dwp
+
+# With linker relaxation, differences of labels are emitted as pairs of add and subtract
+# relocations (including the unit length). The `DW_AT_GNU_dwo_name` of the skeleton unit is only
+# at the right offset in `.debug_str` once its pair of relocations is applied.
+
+# RELOCS-LABEL: .rela.debug_info {
+# RELOCS-NEXT: 0x0 R_RISCV_ADD32 .Ldebug_info_end 0x0
+# RELOCS-NEXT: 0x0 R_RISCV_SUB32 .Ldebug_info_start 0x0
+# RELOCS-NEXT: 0x6 R_RISCV_32 .Ldebug_abbrev 0x0
+# RELOCS-NEXT: 0xC R_RISCV_ADD32 .Ltext_b 0x0
+# RELOCS-NEXT: 0xC R_RISCV_SUB32 .Ltext_a 0x0
+# RELOCS-NEXT: 0x18 R_RISCV_32 .Lcomp_dir 0x0
+
+# Six-bit relocations apply to values which thorin doesn't read (e.g. in `.debug_frame` or the
+# line programs of `.debug_line`), so they are ignored rather than being an error.
+
+# RELOCS-LABEL: .rela.debug_line {
+# RELOCS-NEXT: 0x0 R_RISCV_SET6 .Ltext_b 0x0
+# RELOCS-NEXT: 0x0 R_RISCV_SUB6 .Ltext_a 0x0
+
+# CHECK-LABEL: .debug_info.dwo contents:
+# CHECK: DW_AT_name ("a.cpp")
+
+	.text
+.Ltext_a:
+	nop
+	nop
+.Ltext_b:
+
+	.section	.debug_str,"MS",@progbits,1
+.Lcomp_dir:
+	.asciz	"/absent"
+.Ldwo_name:
+	.asciz	"dwos-list-from-exec-a.dwo"
+
+	.section	.debug_abbrev,"",@progbits
+.Ldebug_abbrev:
+	.byte	1                               # Abbreviation Code
+	.byte	17                              # DW_TAG_compile_unit
+	.byte	0                               # DW_CHILDREN_no
+	.ascii	"\260B"                         # DW_AT_GNU_dwo_name
+	.byte	14                              # DW_FORM_strp
+	.ascii	"\261B"                         # DW_AT_GNU_dwo_id
+	.byte	7                               # DW_FORM_data8
+	.byte	27                              # DW_AT_comp_dir
+	.byte	14                              # DW_FORM_strp
+	.byte	0                               # EOM(1)
+	.byte	0                               # EOM(2)
+	.byte	0                               # EOM(3)
+
+	.section	.debug_info,"",@progbits
+	.long	.Ldebug_info_end - .Ldebug_info_start # Length of Unit
+.Ldebug_info_start:
+	.short	4                               # DWARF version number
+	.long	.Ldebug_abbrev                  # Offset Into Abbrev. Section
+	.byte	8                               # Address Size (in bytes)
+	.byte	1                               # Abbrev [1] DW_TAG_compile_unit
+	.long	.Ltext_b - .Ltext_a             # DW_AT_GNU_dwo_name
+	.quad	0x701370f52cca410e              # DW_AT_GNU_dwo_id
+	.long	.Lcomp_dir                      # DW_AT_comp_dir
+.Ldebug_info_end:
+
+	.section	.debug_line,"",@progbits
+.Ldebug_line:
+	.reloc	.Ldebug_line, R_RISCV_SET6, .Ltext_b
+	.reloc	.Ldebug_line, R_RISCV_SUB6, .Ltext_a
+	.byte	0
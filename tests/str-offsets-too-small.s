@@ -0,0 +1,37 @@
+# RUN: llvm-mc --triple=x86_64-unknown-linux --filetype=obj --split-dwarf-file=%t.dwo -dwarf-version=5 %s -o %t.o
+# RUN: not thorin %t.dwo -o %t.dwp 2>&1 | FileCheck %s
+
+# Checks that a DWARF 5 `.debug_str_offsets.dwo` section which is shorter than its header is
+# reported as an error.
+
+# CHECK: Error: Failed to add `{{.*}}/str-offsets-too-small.s.tmp.dwo` to DWARF package
+# CHECK:  0: Error in `{{.*}}`, section `.debug_str_offsets.dwo`
+# CHECK:  1: DWARF 5 `.debug_str_offsets.dwo` section with size 0x4 is too small to contain its header
+
+	.section	.debug_str_offsets.dwo,"e",@progbits
+	.long	0
+
+	.section	.debug_str.dwo,"MSe",@progbits,1
+	.asciz	"int.dwo"
+
+	.section	.debug_info.dwo,"e",@progbits
+	.long	.Ldebug_info_dwo_end0-.Ldebug_info_dwo_start0 # Length of Unit
+.Ldebug_info_dwo_start0:
+	.short	5                       # DWARF version number
+	.byte	5                       # DWARF Unit Type
+	.byte	8                       # Address Size (in bytes)
+	.long	0                       # Offset Into Abbrev. Section
+	.quad	-1173350285159172090
+	.byte	1                       # Abbrev [1] DW_TAG_compile_unit
+	.byte	0                       # DW_AT_dwo_name
+.Ldebug_info_dwo_end0:
+
+	.section	.debug_abbrev.dwo,"e",@progbits
+	.byte	1                       # Abbreviation Code
+	.byte	17                      # DW_TAG_compile_unit
+	.byte	0                       # DW_CHILDREN_no
+	.byte	118                     # DW_AT_dwo_name
+	.byte	37                      # DW_FORM_strx1
+	.byte	0                       # EOM(1)
+	.byte	0                       # EOM(2)
+	.byte	0                       # EOM(3)
@@ -3,6 +3,13 @@
 # RUN: thorin %t.dwo -o %t.dwp
 # RUN: llvm-dwarfdump -debug-info -debug-tu-index %t.dwp | FileCheck %s
 
+# DWARF packages with only type units don't have a `.debug_cu_index`, and can still be inspected.
+# RUN: llvm-mc -triple x86_64-unknown-linux %s -filetype=obj -o %t-tu-only.o \
+# RUN:   -split-dwarf-file=%t-tu-only.dwo -dwarf-version=5 --defsym TU_ONLY=1
+# RUN: thorin %t-tu-only.dwo -o %t-tu-only.dwp
+# RUN: llvm-objdump -h %t-tu-only.dwp | FileCheck --check-prefix=TU-ONLY-SECTIONS %s
+# RUN: thorin inspect %t-tu-only.dwp | FileCheck --check-prefix=TU-ONLY %s
+
 # CHECK-DAG: .debug_info.dwo contents:
 # CHECK: 0x00000000: Type Unit: length = 0x00000017, format = DWARF32, version = 0x0005, unit_type = DW_UT_split_type, abbr_offset = 0x0000, addr_size = 0x08, name = '', type_signature = [[TUID1:.*]], type_offset = 0x0019 (next unit at 0x0000001b)
 # CHECK: 0x0000001b: Type Unit: length = 0x00000017, format = DWARF32, version = 0x0005, unit_type = DW_UT_split_type, abbr_offset = 0x0000, addr_size = 0x08, name = '', type_signature = [[TUID2:.*]], type_offset = 0x0019 (next unit at 0x00000036)
@@ -12,6 +19,15 @@
 # CHECK:     1 [[TUID1]]          [0x00000000, 0x0000001b) [0x00000000, 0x00000010)
 # CHECK:     4 [[TUID2]]          [0x0000001b, 0x00000036) [0x00000000, 0x00000010)
 
+# TU-ONLY-SECTIONS-NOT: .debug_cu_index
+# TU-ONLY-SECTIONS: .debug_tu_index
+# TU-ONLY-SECTIONS-NOT: .debug_cu_index
+
+# TU-ONLY: index version 5
+# TU-ONLY-EMPTY:
+# TU-ONLY-NEXT: type unit 0x4e834ea939695c24 (DW_UT_split_type, version 5)
+# TU-ONLY: type unit 0x89a49a5d44b29ee7 (DW_UT_split_type, version 5)
+
     .section	.debug_info.dwo,"e",@progbits
     .long	.Ldebug_info_dwo_end0-.Ldebug_info_dwo_start0 # Length of Unit
 .Ldebug_info_dwo_start0:
@@ -38,6 +54,7 @@
     .byte	5                               # Abbrev [5] DW_TAG_structure_type
     .byte	0                               # End Of Children Mark
 .Ldebug_info_dwo_end1:
+.ifndef TU_ONLY
     .section	.debug_info.dwo,"e",@progbits
     .long	.Ldebug_info_dwo_end2-.Ldebug_info_dwo_start2 # Length of Unit
 .Ldebug_info_dwo_start2:
@@ -48,6 +65,7 @@
     .quad	0
     .byte	1                               # Abbrev [1] DW_TAG_compile_unit
 .Ldebug_info_dwo_end2:
+.endif
     .section	.debug_abbrev.dwo,"e",@progbits
     .byte	1                               # Abbreviation Code
     .byte	17                              # DW_TAG_compile_unit
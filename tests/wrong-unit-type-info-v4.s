@@ -2,8 +2,9 @@
 # RUN: not thorin %t.dwp -o %t 2>&1 | FileCheck %s
 
 # CHECK: Error: Failed to add `{{.*}}/wrong-unit-type-info-v4.s.tmp.dwp` to DWARF package
-# CHECK:  0: Failed to parse unit
-# CHECK:  1: Hit the end of input before it was expected
+# CHECK:  0: Error in `{{.*}}`, section `.debug_info.dwo` at offset 0x0
+# CHECK:  1: Failed to parse unit
+# CHECK:  2: Hit the end of input before it was expected
 
   .section	.debug_info.dwo,"e",@progbits
   .long	.Ldebug_info_dwo_end0-.Ldebug_info_dwo_start0 # Length of Unit
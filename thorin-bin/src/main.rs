@@ -3,6 +3,7 @@ use std::{
     ffi::OsStr,
     fs::{File, OpenOptions},
     io::{self, BufWriter, Write},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
 };
 
@@ -20,28 +21,248 @@ use typed_arena::Arena;
 enum Error {
     #[error("Failed to add `{0}` to DWARF package")]
     AddInputObject(String),
+    #[cfg(feature = "rayon")]
+    #[error("Failed to create thread pool with {0} threads")]
+    CreateThreadPool(usize),
     #[error("Failed to add referenced DWARF object/packages from `{0}` to DWARF package")]
     AddExecutable(String),
+    #[error("Failed to remove units of `{0}` from DWARF package")]
+    RemoveInputObject(String),
+    #[error("Failed to select units referenced by `{0}`")]
+    SelectReferencedUnits(String),
     #[error("Failed to create output object file at `{0}`")]
     CreateOutputFile(String),
     #[error("Failed verifying final DWARF package")]
     Finish,
     #[error("Failed writing output object to output buffer")]
     EmitOutputObject,
+    #[error("Failed writing shards of DWARF package")]
+    FinishShards,
+    #[error("Failed writing shard manifest to `{0}`")]
+    WriteManifest(String),
+    #[error("Failed verifying or writing streamed DWARF package")]
+    FinishStreaming,
+    #[error("Failed to read DWARF package `{0}`")]
+    ReadPackage(String),
+    #[error("Failed to read unit {0} from DWARF package")]
+    DescribeUnit(String),
+    #[error("Failed writing units of DWARF package to output")]
+    PrintUnits,
+    #[error("Failed writing statistics about DWARF package")]
+    PrintStatistics,
+    #[error("Failed to verify DWARF package `{0}`")]
+    Verify(String),
+    #[error("Failed writing verification problems to output")]
+    PrintProblems,
+    #[error("DWARF package `{0}` failed verification with {1} problem(s)")]
+    VerificationFailed(String, usize),
 }
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "thorin", about = "merge dwarf objects into dwarf packages")]
 struct Opt {
+    #[structopt(subcommand)]
+    command: Option<Command>,
     /// Specify path to input dwarf objects and packages
     #[structopt(parse(from_os_str))]
     inputs: Vec<PathBuf>,
-    /// Specify path to executables to read list of dwarf objects from
+    /// Specify number of threads used to prepare input objects (defaults to the number of cpus,
+    /// one prepares input objects serially)
+    #[cfg(feature = "rayon")]
+    #[structopt(short = "j", long = "jobs")]
+    jobs: Option<NonZeroUsize>,
+    /// Specify path to executables (or archives of objects) to read list of dwarf objects from
     #[structopt(short = "e", long = "exec", parse(from_os_str))]
     executables: Option<Vec<PathBuf>>,
     /// Specify path to write the dwarf package to
     #[structopt(short = "o", long = "output", parse(from_os_str), default_value = "-")]
     output: PathBuf,
+    /// Specify object file format of the dwarf package (defaults to the format of the first input)
+    #[structopt(
+        long = "output-format",
+        possible_values = &["elf", "macho"],
+        parse(try_from_str = parse_output_format)
+    )]
+    output_format: Option<thorin::OutputFormat>,
+    /// Specify directory to write sections of the dwarf package to as temporary files, rather than
+    /// buffering the dwarf package in memory (only for elf dwarf packages)
+    #[structopt(long = "streaming-output", parse(from_os_str))]
+    streaming_output: Option<PathBuf>,
+    /// Specify directory to search for dwarf objects referenced by executables which aren't at the
+    /// path recorded in the executable (the directory containing the executable is always searched)
+    #[structopt(long = "search-dir", number_of_values = 1, parse(from_os_str))]
+    search_dirs: Vec<PathBuf>,
+    /// Specify directory to search for separate debug files of stripped executables, by build id
+    /// and debuglink (defaults to `/usr/lib/debug`)
+    #[structopt(long = "debug-dir", number_of_values = 1, parse(from_os_str))]
+    debug_dirs: Vec<PathBuf>,
+    /// Specify `from=to` to replace the prefix `from` with `to` in paths to dwarf objects recorded
+    /// in executables
+    #[structopt(
+        long = "remap-path-prefix",
+        number_of_values = 1,
+        parse(try_from_str = parse_path_remapping)
+    )]
+    path_remappings: Vec<(PathBuf, PathBuf)>,
+    /// Specify compression of the sections of the dwarf package (zlib, or zstd when built with the
+    /// zstd feature)
+    #[structopt(
+        long = "compress-debug-sections",
+        parse(try_from_str = parse_output_compression)
+    )]
+    compress_debug_sections: Option<thorin::OutputCompression>,
+    /// Leave the index sections of a compressed dwarf package uncompressed
+    #[structopt(long = "uncompressed-index")]
+    uncompressed_index: bool,
+    /// Write a `.note.gnu.build-id` section with the build id of each executable to the dwarf
+    /// package (only for elf dwarf packages)
+    #[structopt(long = "build-id-note")]
+    build_id_note: bool,
+    /// Print warnings (e.g. about skipped inputs or duplicate type units) to stderr
+    #[structopt(long = "warnings")]
+    warnings: bool,
+    /// Print statistics about the dwarf package (e.g. deduplicated type units and section sizes)
+    /// to stderr
+    #[structopt(long = "stats")]
+    stats: bool,
+    /// Compare duplicate type units with the type unit in the dwarf package, printing a warning to
+    /// stderr if they differ
+    #[structopt(long = "compare-type-units")]
+    compare_type_units: bool,
+    /// Specify how compilation units with the same dwo id as a unit already in the dwarf package are
+    /// handled
+    #[structopt(
+        long = "duplicate-units",
+        possible_values = &["error", "keep-first", "keep-last", "keep-if-identical"],
+        parse(try_from_str = parse_duplicate_unit_behaviour)
+    )]
+    duplicate_units: Option<thorin::DuplicateUnitBehaviour>,
+    /// Split the dwarf package into this many shards by dwo id, writing each shard to
+    /// `<output>.<shard>.<ext>` and a manifest of the shard of each unit to `<output>.manifest`
+    #[structopt(long = "shards", conflicts_with_all = &["shard-size", "stats"])]
+    shards: Option<NonZeroUsize>,
+    /// Split the dwarf package into shards of approximately at most this many bytes, writing each
+    /// shard to `<output>.<shard>.<ext>` and a manifest of the shard of each unit to
+    /// `<output>.manifest`
+    #[structopt(long = "shard-size", conflicts_with = "stats")]
+    shard_size: Option<u64>,
+    /// Specify existing dwarf package to update, replacing its units with units from the inputs
+    #[structopt(long = "update", parse(from_os_str))]
+    update: Option<PathBuf>,
+    /// Specify path to dwarf objects whose units should be removed from the dwarf package being
+    /// updated
+    #[structopt(long = "remove", number_of_values = 1, parse(from_os_str))]
+    removed: Vec<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Print the units in a dwarf package and their contributions to each section
+    Inspect(InspectOpt),
+    /// Check a dwarf package against the executables which reference it
+    Verify(VerifyOpt),
+    /// Create a dwarf package containing only the selected compilation units of a dwarf package
+    /// (and the type units they reference)
+    #[structopt(alias = "strip")]
+    Filter(FilterOpt),
+}
+
+#[derive(Debug, StructOpt)]
+struct InspectOpt {
+    /// Specify path to the dwarf package to inspect
+    #[structopt(parse(from_os_str))]
+    package: PathBuf,
+    /// Print units as json
+    #[structopt(long = "json")]
+    json: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct VerifyOpt {
+    /// Specify path to the dwarf package to verify
+    #[structopt(parse(from_os_str))]
+    package: PathBuf,
+    /// Specify path to executables to check the dwarf package against
+    #[structopt(short = "e", long = "exec", number_of_values = 1, parse(from_os_str))]
+    executables: Vec<PathBuf>,
+    /// Specify directory to search for separate debug files of stripped executables, by build id
+    /// and debuglink (defaults to `/usr/lib/debug`)
+    #[structopt(long = "debug-dir", number_of_values = 1, parse(from_os_str))]
+    debug_dirs: Vec<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct FilterOpt {
+    /// Specify path to the dwarf package to filter
+    #[structopt(parse(from_os_str))]
+    package: PathBuf,
+    /// Specify path to write the filtered dwarf package to
+    #[structopt(short = "o", long = "output", parse(from_os_str), default_value = "-")]
+    output: PathBuf,
+    /// Select the compilation unit with this dwo id (in hexadecimal)
+    #[structopt(long = "dwo-id", number_of_values = 1, parse(try_from_str = parse_dwo_id))]
+    dwo_ids: Vec<thorin::DwoId>,
+    /// Select compilation units whose name matches this pattern (`*` matches any sequence of
+    /// characters and `?` matches any single character)
+    #[structopt(long = "name", number_of_values = 1)]
+    names: Vec<String>,
+    /// Select the compilation units referenced by this executable
+    #[structopt(short = "e", long = "exec", number_of_values = 1, parse(from_os_str))]
+    executables: Vec<PathBuf>,
+    /// Specify directory to search for separate debug files of stripped executables, by build id
+    /// and debuglink (defaults to `/usr/lib/debug`)
+    #[structopt(long = "debug-dir", number_of_values = 1, parse(from_os_str))]
+    debug_dirs: Vec<PathBuf>,
+    /// Write a `.note.gnu.build-id` section with the build id of each executable to the dwarf
+    /// package (only for elf dwarf packages)
+    #[structopt(long = "build-id-note")]
+    build_id_note: bool,
+}
+
+/// Parse a dwo id (in hexadecimal, optionally prefixed with `0x`) from the command-line.
+fn parse_dwo_id(dwo_id: &str) -> Result<thorin::DwoId> {
+    let digits = dwo_id.strip_prefix("0x").unwrap_or(dwo_id);
+    u64::from_str_radix(digits, 16)
+        .map(thorin::DwoId)
+        .map_err(|_| anyhow::anyhow!("dwo id `{}` must be hexadecimal", dwo_id))
+}
+
+/// Parse an output format from the command-line.
+fn parse_output_format(format: &str) -> Result<thorin::OutputFormat> {
+    match format {
+        "elf" => Ok(thorin::OutputFormat::Elf),
+        "macho" => Ok(thorin::OutputFormat::MachO),
+        _ => Err(anyhow::anyhow!("unknown output format `{}`", format)),
+    }
+}
+
+/// Parse a duplicate unit behaviour from the command-line.
+fn parse_duplicate_unit_behaviour(behaviour: &str) -> Result<thorin::DuplicateUnitBehaviour> {
+    match behaviour {
+        "error" => Ok(thorin::DuplicateUnitBehaviour::Error),
+        "keep-first" => Ok(thorin::DuplicateUnitBehaviour::KeepFirst),
+        "keep-last" => Ok(thorin::DuplicateUnitBehaviour::KeepLast),
+        "keep-if-identical" => Ok(thorin::DuplicateUnitBehaviour::KeepIfIdentical),
+        _ => Err(anyhow::anyhow!("unknown duplicate unit behaviour `{}`", behaviour)),
+    }
+}
+
+/// Parse an output compression from the command-line.
+fn parse_output_compression(compression: &str) -> Result<thorin::OutputCompression> {
+    match compression {
+        "zlib" => Ok(thorin::OutputCompression::Zlib),
+        #[cfg(feature = "zstd")]
+        "zstd" => Ok(thorin::OutputCompression::Zstd),
+        _ => Err(anyhow::anyhow!("unknown or unsupported compression `{}`", compression)),
+    }
+}
+
+/// Parse a path prefix remapping (`from=to`) from the command-line.
+fn parse_path_remapping(remapping: &str) -> Result<(PathBuf, PathBuf)> {
+    match remapping.rsplit_once('=') {
+        Some((from, to)) => Ok((PathBuf::from(from), PathBuf::from(to))),
+        None => Err(anyhow::anyhow!("path prefix remapping `{}` must be `from=to`", remapping)),
+    }
 }
 
 /// Implementation of `thorin::Session` using `typed_arena` and `memmap2`.
@@ -50,28 +271,36 @@ struct Session<Relocations> {
     arena_data: Arena<Vec<u8>>,
     arena_mmap: Arena<Mmap>,
     arena_relocations: Arena<Relocations>,
+    print_warnings: bool,
 }
 
 impl<Relocations> Session<Relocations> {
-    fn alloc_mmap<'arena>(&'arena self, data: Mmap) -> &'arena Mmap {
+    fn alloc_mmap(&self, data: Mmap) -> &Mmap {
         (*self.arena_mmap.alloc(data)).borrow()
     }
 }
 
 impl<Relocations> thorin::Session<Relocations> for Session<Relocations> {
-    fn alloc_data<'arena>(&'arena self, data: Vec<u8>) -> &'arena [u8] {
+    fn alloc_data(&self, data: Vec<u8>) -> &[u8] {
         (*self.arena_data.alloc(data)).borrow()
     }
 
-    fn alloc_relocation<'arena>(&'arena self, data: Relocations) -> &'arena Relocations {
+    fn alloc_relocation(&self, data: Relocations) -> &Relocations {
         (*self.arena_relocations.alloc(data)).borrow()
     }
 
     fn read_input<'arena>(&'arena self, path: &Path) -> std::io::Result<&'arena [u8]> {
-        let file = File::open(&path)?;
+        let file = File::open(path)?;
         let mmap = (unsafe { Mmap::map(&file) })?;
         Ok(self.alloc_mmap(mmap))
     }
+
+    fn warn(&self, warning: thorin::Warning) {
+        // Mismatched type units are only reported when requested with `--compare-type-units`.
+        if self.print_warnings || matches!(warning, thorin::Warning::MismatchedTypeUnit(..)) {
+            eprintln!("warning: {}", warning);
+        }
+    }
 }
 
 /// Returns `true` if the file type is a fifo.
@@ -142,20 +371,93 @@ fn main() -> Result<()> {
     let opt = Opt::from_args();
     trace!(?opt);
 
-    let sess = Session::default();
+    match &opt.command {
+        Some(Command::Inspect(inspect_opt)) => return inspect(inspect_opt),
+        Some(Command::Verify(verify_opt)) => return verify(verify_opt),
+        Some(Command::Filter(filter_opt)) => return filter(filter_opt),
+        None => (),
+    }
+
+    let sess = Session { print_warnings: opt.warnings, ..Default::default() };
     let mut package = thorin::DwarfPackage::new(&sess);
+    if let Some(format) = opt.output_format {
+        package = package.with_output_format(format);
+    }
+    let streaming = opt.streaming_output.is_some();
+    if let Some(dir) = opt.streaming_output {
+        package = package.with_streaming_output(dir);
+    }
+    if let Some(compression) = opt.compress_debug_sections {
+        package = package.with_output_compression(compression);
+    }
+    if opt.uncompressed_index {
+        package = package.with_uncompressed_index_sections();
+    }
+    if opt.build_id_note {
+        package = package.with_build_id_note();
+    }
+    if opt.compare_type_units {
+        package = package.with_type_unit_comparison();
+    }
+    if let Some(behaviour) = opt.duplicate_units {
+        package = package.with_duplicate_unit_behaviour(behaviour);
+    }
+    for dir in opt.search_dirs {
+        package = package.with_search_dir(dir);
+    }
+    for dir in opt.debug_dirs {
+        package = package.with_debug_dir(dir);
+    }
+    for (from, to) in opt.path_remappings {
+        package = package.with_path_remapping(from, to);
+    }
+    let sharding = match (opt.shards, opt.shard_size) {
+        (Some(count), _) => Some(thorin::Sharding::ByDwoId(count)),
+        (None, Some(size)) => Some(thorin::Sharding::BySize(size)),
+        (None, None) => None,
+    };
+    if let Some(sharding) = sharding {
+        if opt.output.as_os_str() == "-" {
+            anyhow::bail!("sharded dwarf packages must be written to an output path with `-o`");
+        }
+        package = package.with_sharding(sharding);
+    }
+    let updating = opt.update.is_some();
+    if let Some(path) = opt.update {
+        package = package.with_package_to_update(path);
+    }
 
     // Return early if there isn't any input.
-    if opt.inputs.is_empty() && opt.executables.is_none() {
+    if opt.inputs.is_empty() && opt.executables.is_none() && !updating {
         return Ok(());
     }
 
-    for input in opt.inputs {
+    for removed in opt.removed {
         package
-            .add_input_object(&input)
-            .with_context(|| Error::AddInputObject(input.display().to_string()))?;
+            .remove_input_object(&removed)
+            .with_context(|| Error::RemoveInputObject(removed.display().to_string()))?;
+    }
+
+    #[cfg(feature = "rayon")]
+    match opt.jobs {
+        Some(jobs) if jobs.get() == 1 => add_input_objects(&mut package, &opt.inputs)?,
+        jobs => {
+            if let Some(jobs) = jobs {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(jobs.get())
+                    .build_global()
+                    .with_context(|| Error::CreateThreadPool(jobs.get()))?;
+            }
+            package.add_input_objects(&opt.inputs).map_err(|e| {
+                let path = e.location().map(|location| location.path.display().to_string());
+                input_context(e, || Error::AddInputObject(path.unwrap_or_default()))
+            })?;
+        }
     }
 
+    #[cfg(not(feature = "rayon"))]
+    add_input_objects(&mut package, &opt.inputs)?;
+
     if let Some(executables) = opt.executables {
         for executable in executables {
             // Failing to read the referenced object might be expected if the path referenced by
@@ -163,18 +465,295 @@ fn main() -> Result<()> {
             // input - calling `finish` will return an error in this case.
             package
                 .add_executable(&executable, thorin::MissingReferencedObjectBehaviour::Skip)
-                .with_context(|| Error::AddExecutable(executable.display().to_string()))?;
+                .map_err(|e| {
+                    input_context(e, || Error::AddExecutable(executable.display().to_string()))
+                })?;
         }
     }
 
+    if sharding.is_some() {
+        return finish_shards(package, &opt.output, streaming);
+    }
+
+    let output_stream = Output::new(opt.output.as_ref())
+        .with_context(|| Error::CreateOutputFile(opt.output.display().to_string()))?;
+    let statistics = if streaming {
+        let mut output_stream = BufWriter::new(output_stream);
+        let statistics = package
+            .finish_to_with_statistics(&mut output_stream)
+            .context(Error::FinishStreaming)?;
+        output_stream.flush().context(Error::EmitOutputObject)?;
+        statistics
+    } else {
+        let mut output_stream = StreamingBuffer::new(BufWriter::new(output_stream));
+        let (obj, statistics) = package.finish_with_statistics().context(Error::Finish)?;
+        obj.emit(&mut output_stream).context(Error::EmitOutputObject)?;
+        output_stream.result().context(Error::EmitOutputObject)?;
+        output_stream.into_inner().flush().context(Error::EmitOutputObject)?;
+        statistics
+    };
+
+    if opt.stats {
+        print_statistics(&statistics).context(Error::PrintStatistics)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the path of shard `shard` of a sharded DWARF package written to `output`
+/// (`<output>.<shard>.<ext>`).
+fn shard_path(output: &Path, shard: usize) -> PathBuf {
+    let mut name = output.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", shard));
+    if let Some(extension) = output.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    output.with_file_name(name)
+}
+
+/// Write each shard of a sharded DWARF package next to `output` (see `shard_path`) and the shard
+/// manifest to `<output>.manifest`.
+fn finish_shards(
+    package: thorin::DwarfPackage<'_, '_, Session<thorin::RelocationMap>>,
+    output: &Path,
+    streaming: bool,
+) -> Result<()> {
+    let create_shard = |shard| {
+        let path = shard_path(output, shard);
+        Output::new(path.as_ref())
+            .with_context(|| Error::CreateOutputFile(path.display().to_string()))
+    };
+
+    let manifest = if streaming {
+        let mut error = None;
+        let manifest = package.finish_shards_to(|shard| match create_shard(shard) {
+            Ok(output_stream) => Ok(BufWriter::new(output_stream)),
+            Err(e) => {
+                let io_error = io::Error::other(e.to_string());
+                error = Some(e);
+                Err(io_error)
+            }
+        });
+        if let Some(e) = error {
+            return Err(e);
+        }
+        manifest.context(Error::FinishStreaming)?
+    } else {
+        let (objs, manifest) = package.finish_shards().context(Error::Finish)?;
+        for (shard, obj) in objs.into_iter().enumerate() {
+            let mut output_stream = StreamingBuffer::new(BufWriter::new(create_shard(shard)?));
+            obj.emit(&mut output_stream).context(Error::FinishShards)?;
+            output_stream.result().context(Error::FinishShards)?;
+            output_stream.into_inner().flush().context(Error::FinishShards)?;
+        }
+        manifest
+    };
+
+    let manifest_path = output.with_extension("manifest");
+    let manifest_output = File::create(&manifest_path)
+        .with_context(|| Error::CreateOutputFile(manifest_path.display().to_string()))?;
+    let mut manifest_output = BufWriter::new(manifest_output);
+    manifest
+        .write(&mut manifest_output)
+        .and_then(|()| manifest_output.flush())
+        .with_context(|| Error::WriteManifest(manifest_path.display().to_string()))
+}
+
+/// Print statistics about a DWARF package to stderr, as printed by `thorin --stats`.
+fn print_statistics(statistics: &thorin::PackageStatistics) -> io::Result<()> {
+    let stderr = io::stderr();
+    let mut output = stderr.lock();
+    writeln!(output, "input objects: {}", statistics.input_objects)?;
+    writeln!(output, "compilation units: {}", statistics.compilation_units)?;
+    writeln!(output, "type units: {}", statistics.type_units)?;
+    writeln!(
+        output,
+        "duplicate type units: {} ({} bytes)",
+        statistics.duplicate_type_units, statistics.duplicate_type_unit_bytes
+    )?;
+    writeln!(output, "duplicate string bytes: {}", statistics.duplicate_string_bytes)?;
+    writeln!(output, "section sizes:")?;
+    for (name, size) in &statistics.section_sizes {
+        writeln!(output, "  {}: {}", name, size)?;
+    }
+    Ok(())
+}
+
+/// Returns the kind and identifier of a unit, as printed by `thorin inspect`.
+fn unit_kind_and_id(id: thorin::DwarfObject) -> (&'static str, String) {
+    match id {
+        thorin::DwarfObject::Compilation(thorin::DwoId(id)) => {
+            ("compile", format!("0x{:016x}", id))
+        }
+        thorin::DwarfObject::Type(thorin::DebugTypeSignature(id)) => {
+            ("type", format!("0x{:016x}", id))
+        }
+    }
+}
+
+/// Print the units in a DWARF package, their descriptions and their contributions.
+fn inspect(opt: &InspectOpt) -> Result<()> {
+    let sess = Session::default();
+    let reader = thorin::DwarfPackageReader::new(&sess, &opt.package)
+        .with_context(|| Error::ReadPackage(opt.package.display().to_string()))?;
+
+    let mut units = Vec::new();
+    for unit in reader.units() {
+        let description = reader
+            .describe_unit(unit)
+            .with_context(|| Error::DescribeUnit(unit_kind_and_id(unit.id()).1))?;
+        units.push((unit, description));
+    }
+
+    let stdout = io::stdout();
+    let mut output = BufWriter::new(stdout.lock());
+    if opt.json {
+        let units: Vec<_> = units
+            .iter()
+            .map(|(unit, description)| {
+                let (kind, id) = unit_kind_and_id(unit.id());
+                let contributions: Vec<_> = unit
+                    .contributions()
+                    .iter()
+                    .map(|contribution| {
+                        serde_json::json!({
+                            "section": contribution.section_name(),
+                            "offset": contribution.offset,
+                            "size": contribution.size,
+                        })
+                    })
+                    .collect();
+                serde_json::json!({
+                    "kind": kind,
+                    "id": id,
+                    "unit_type": description.unit_type.to_string(),
+                    "version": description.version,
+                    "producer": description.producer,
+                    "name": description.name,
+                    "contributions": contributions,
+                })
+            })
+            .collect();
+        let package = serde_json::json!({
+            "index_version": reader.index_version(),
+            "units": units,
+        });
+        serde_json::to_writer_pretty(&mut output, &package).context(Error::PrintUnits)?;
+        writeln!(output).context(Error::PrintUnits)?;
+    } else {
+        writeln!(output, "index version {}", reader.index_version()).context(Error::PrintUnits)?;
+        for (unit, description) in &units {
+            let (kind, id) = unit_kind_and_id(unit.id());
+            writeln!(
+                output,
+                "\n{} unit {} ({}, version {})",
+                kind, id, description.unit_type, description.version
+            )
+            .context(Error::PrintUnits)?;
+            if let Some(name) = &description.name {
+                writeln!(output, "  name: {}", name).context(Error::PrintUnits)?;
+            }
+            if let Some(producer) = &description.producer {
+                writeln!(output, "  producer: {}", producer).context(Error::PrintUnits)?;
+            }
+            for contribution in unit.contributions() {
+                writeln!(
+                    output,
+                    "  {:<24} offset 0x{:08x} size 0x{:08x}",
+                    contribution.section_name(),
+                    contribution.offset,
+                    contribution.size
+                )
+                .context(Error::PrintUnits)?;
+            }
+        }
+    }
+
+    output.flush().context(Error::PrintUnits)
+}
+
+/// Create a DWARF package containing the units of a DWARF package which are selected by their
+/// `DwoId`, name or by an executable.
+fn filter(opt: &FilterOpt) -> Result<()> {
+    let sess = Session::default();
+    let mut unit_filter = thorin::UnitFilter::new();
+    for dwo_id in &opt.dwo_ids {
+        unit_filter = unit_filter.with_dwo_id(*dwo_id);
+    }
+    for name in &opt.names {
+        unit_filter = unit_filter.with_name_pattern(name.clone());
+    }
+
+    let mut package = thorin::DwarfPackage::new(&sess).with_unit_filter(unit_filter);
+    for dir in &opt.debug_dirs {
+        package = package.with_debug_dir(dir.clone());
+    }
+    if opt.build_id_note {
+        package = package.with_build_id_note();
+    }
+    for executable in &opt.executables {
+        package.select_units_referenced_by(executable).map_err(|e| {
+            input_context(e, || Error::SelectReferencedUnits(executable.display().to_string()))
+        })?;
+    }
+    package.add_input_object(&opt.package).map_err(|e| {
+        input_context(e, || Error::AddInputObject(opt.package.display().to_string()))
+    })?;
+
     let output_stream = Output::new(opt.output.as_ref())
         .with_context(|| Error::CreateOutputFile(opt.output.display().to_string()))?;
     let mut output_stream = StreamingBuffer::new(BufWriter::new(output_stream));
-    package
-        .finish()
-        .context(Error::Finish)?
-        .emit(&mut output_stream)
-        .context(Error::EmitOutputObject)?;
+    let obj = package.finish().context(Error::Finish)?;
+    obj.emit(&mut output_stream).context(Error::EmitOutputObject)?;
     output_stream.result().context(Error::EmitOutputObject)?;
     output_stream.into_inner().flush().context(Error::EmitOutputObject)
 }
+
+/// Add each of `inputs` to the DWARF package in turn.
+fn add_input_objects(
+    package: &mut thorin::DwarfPackage<'_, '_, Session<thorin::RelocationMap>>,
+    inputs: &[PathBuf],
+) -> Result<()> {
+    for input in inputs {
+        package
+            .add_input_object(input)
+            .map_err(|e| input_context(e, || Error::AddInputObject(input.display().to_string())))?;
+    }
+    Ok(())
+}
+
+/// Add the context returned by `context` to an error which occurred in an input. Errors with the
+/// DWARF package being created (such as an unsupported output format) don't have a location in an
+/// input and aren't attributed to the input being added.
+fn input_context(e: thorin::Error, context: impl FnOnce() -> Error) -> anyhow::Error {
+    match e.location() {
+        Some(_) => anyhow::Error::new(e).context(context()),
+        None => anyhow::Error::new(e),
+    }
+}
+
+/// Verify a DWARF package against executables, printing every problem found and returning an
+/// error if there were any.
+fn verify(opt: &VerifyOpt) -> Result<()> {
+    let sess = Session::default();
+    let package = opt.package.display().to_string();
+    let reader = thorin::DwarfPackageReader::new(&sess, &opt.package)
+        .with_context(|| Error::ReadPackage(package.clone()))?;
+    let problems = reader
+        .verify_with_debug_dirs(&sess, &opt.executables, &opt.debug_dirs)
+        .with_context(|| Error::Verify(package.clone()))?;
+
+    let stdout = io::stdout();
+    let mut output = BufWriter::new(stdout.lock());
+    for problem in &problems {
+        writeln!(output, "{}", problem).context(Error::PrintProblems)?;
+    }
+    output.flush().context(Error::PrintProblems)?;
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::VerificationFailed(package, problems.len()).into())
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::{Error, Result},
+    package::UnitSections,
+};
+
+/// Value of an attribute of a debugging information entry, independent of the attribute's form
+/// and of the layout of the input that the entry was read from.
+#[derive(Debug, Eq, PartialEq)]
+enum TreeValue {
+    /// Inline string or string in `.debug_str.dwo`.
+    String(Vec<u8>),
+    /// Reference to another entry in the type unit, by the entry's position in the unit.
+    Entry(usize),
+    /// Block or expression.
+    Bytes(Vec<u8>),
+    /// Constant or other unsigned value.
+    Unsigned(u64),
+    /// Signed constant.
+    Signed(i64),
+    /// Any other value, compared using its `Debug` representation.
+    Other(String),
+}
+
+/// Debugging information entry of a type unit, see `TypeUnitTree`.
+#[derive(Debug)]
+struct TreeEntry {
+    /// Offset of the entry in the section containing the type unit.
+    offset: u64,
+    /// Depth of the entry in the tree, the root entry has depth zero.
+    depth: isize,
+    tag: gimli::DwTag,
+    attrs: Vec<(gimli::DwAt, TreeValue)>,
+}
+
+impl TreeEntry {
+    /// Returns `true` if `other` has the same depth, tag and attributes as this entry.
+    fn same_as(&self, other: &TreeEntry) -> bool {
+        self.depth == other.depth && self.tag == other.tag && self.attrs == other.attrs
+    }
+}
+
+/// Debugging information entries of a type unit, in depth-first order, which can be compared with
+/// the entries of a type unit from another input to check that type units with the same
+/// signature are identical.
+///
+/// Strings are read from the input's string tables and references between entries are replaced
+/// with the position of the referenced entry, so that type units from inputs with different
+/// string tables compare equal. `DW_AT_decl_file` (an index into the file names of the input's
+/// line table) and `DW_AT_sibling` (which depends on the layout of the unit) aren't compared.
+#[derive(Debug)]
+pub(crate) struct TypeUnitTree {
+    /// Offset of the type unit in the section containing it.
+    unit_offset: u64,
+    /// Position of the entry describing the type of the type unit.
+    type_entry: Option<usize>,
+    entries: Vec<TreeEntry>,
+}
+
+impl TypeUnitTree {
+    /// Read the type unit in `data`, at offset `unit_offset` of a `.debug_types.dwo` section if
+    /// `is_debug_types` and a `.debug_info.dwo` section otherwise.
+    pub(crate) fn read(
+        data: &[u8],
+        unit_offset: u64,
+        is_debug_types: bool,
+        sections: &UnitSections<'_>,
+    ) -> Result<Self> {
+        let header = sections.read_header(data, is_debug_types)?;
+        let abbreviations = header
+            .abbreviations(&sections.debug_abbrev())
+            .map_err(Error::ParseUnitAbbreviations)?;
+
+        // References can be to later entries, so find the position of every entry first.
+        let mut positions = HashMap::new();
+        let mut cursor = header.entries(&abbreviations);
+        while let Some((_, entry)) = cursor.next_dfs()? {
+            positions.insert(entry.offset(), positions.len());
+        }
+
+        let mut entries = Vec::with_capacity(positions.len());
+        let mut depth = 0;
+        let mut cursor = header.entries(&abbreviations);
+        while let Some((delta_depth, entry)) = cursor.next_dfs()? {
+            depth += delta_depth;
+
+            let mut attrs = Vec::new();
+            let mut iter = entry.attrs();
+            while let Some(attr) = iter.next().map_err(Error::ParseUnitAttribute)? {
+                if matches!(attr.name(), gimli::DW_AT_decl_file | gimli::DW_AT_sibling) {
+                    continue;
+                }
+
+                if let Some(string) = sections.string(attr.value())? {
+                    attrs.push((attr.name(), TreeValue::String(string.to_vec())));
+                    continue;
+                }
+
+                let value = match attr.value() {
+                    gimli::AttributeValue::UnitRef(offset) => match positions.get(&offset) {
+                        Some(position) => TreeValue::Entry(*position),
+                        None => TreeValue::Other(format!("{:?}", attr.value())),
+                    },
+                    gimli::AttributeValue::Block(data)
+                    | gimli::AttributeValue::Exprloc(gimli::Expression(data)) => {
+                        TreeValue::Bytes(data.to_vec())
+                    }
+                    gimli::AttributeValue::Sdata(value) => TreeValue::Signed(value),
+                    value => match value.udata_value() {
+                        Some(value) => TreeValue::Unsigned(value),
+                        None => TreeValue::Other(format!("{:?}", value)),
+                    },
+                };
+                attrs.push((attr.name(), value));
+            }
+
+            entries.push(TreeEntry {
+                offset: unit_offset + entry.offset().0 as u64,
+                depth,
+                tag: entry.tag(),
+                attrs,
+            });
+        }
+
+        let type_entry = match header.type_() {
+            gimli::UnitType::Type { type_offset, .. }
+            | gimli::UnitType::SplitType { type_offset, .. } => {
+                positions.get(&type_offset).copied()
+            }
+            _ => None,
+        };
+
+        Ok(Self { unit_offset, type_entry, entries })
+    }
+
+    /// Returns the offsets of the first entries of this type unit and `other` which differ, or of
+    /// the type units themselves if they differ in some other way (such as in the number of
+    /// entries). Returns `None` if the type units are the same.
+    pub(crate) fn first_difference(&self, other: &TypeUnitTree) -> Option<(u64, u64)> {
+        for (entry, other_entry) in self.entries.iter().zip(&other.entries) {
+            if !entry.same_as(other_entry) {
+                return Some((entry.offset, other_entry.offset));
+            }
+        }
+
+        if self.entries.len() != other.entries.len() || self.type_entry != other.type_entry {
+            return Some((self.unit_offset, other.unit_offset));
+        }
+
+        None
+    }
+}
@@ -0,0 +1,115 @@
+use std::io::{self, Read, Write};
+
+use object::{elf, pod, Endian, Endianness, Object, ObjectSection, SectionFlags};
+
+/// `ELFCOMPRESS_ZSTD` compression type, not yet defined by `object`.
+#[cfg(feature = "zstd")]
+const ELFCOMPRESS_ZSTD: u32 = 2;
+
+/// Compression used for the sections of the output DWARF package.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum OutputCompression {
+    /// Compress sections with zlib (`ELFCOMPRESS_ZLIB`).
+    Zlib,
+    /// Compress sections with zstd (`ELFCOMPRESS_ZSTD`).
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl OutputCompression {
+    /// Returns the `ch_type` of the compression header of sections compressed with this
+    /// compression.
+    fn ch_type(self) -> u32 {
+        match self {
+            OutputCompression::Zlib => elf::ELFCOMPRESS_ZLIB,
+            #[cfg(feature = "zstd")]
+            OutputCompression::Zstd => ELFCOMPRESS_ZSTD,
+        }
+    }
+}
+
+/// Returns the alignment of compressed sections, which is the alignment of their compression
+/// header.
+pub(crate) fn compressed_section_align(is_64: bool) -> u64 {
+    if is_64 {
+        8
+    } else {
+        4
+    }
+}
+
+/// Returns the alignment of the uncompressed contents of `section` in `obj`: the `ch_addralign` of
+/// the compression header of `SHF_COMPRESSED` sections, otherwise the alignment of the section.
+pub(crate) fn uncompressed_section_align<'data: 'file, 'file, O, S>(
+    obj: &'file O,
+    section: &S,
+) -> u64
+where
+    O: Object<'data, 'file>,
+    S: ObjectSection<'data>,
+{
+    let is_compressed = matches!(
+        section.flags(),
+        SectionFlags::Elf { sh_flags } if sh_flags & u64::from(elf::SHF_COMPRESSED) != 0
+    );
+    let data = match section.data() {
+        Ok(data) if is_compressed => data,
+        _ => return section.align(),
+    };
+
+    // Malformed compression headers are reported when the section is decompressed.
+    let endian = obj.endianness();
+    let align = if obj.is_64() {
+        pod::from_bytes::<elf::CompressionHeader64<Endianness>>(data)
+            .map(|(header, _)| header.ch_addralign.get(endian))
+    } else {
+        pod::from_bytes::<elf::CompressionHeader32<Endianness>>(data)
+            .map(|(header, _)| header.ch_addralign.get(endian).into())
+    };
+    align.unwrap_or_else(|_| section.align())
+}
+
+/// Write the contents of a `SHF_COMPRESSED` section to `output`: a compression header describing
+/// `size` bytes of uncompressed data with alignment `align`, followed by `data` compressed with
+/// `compression`. Returns `output` once all compressed data has been written to it.
+pub(crate) fn compress_section<R: Read, W: Write>(
+    compression: OutputCompression,
+    endianness: Endianness,
+    is_64: bool,
+    size: u64,
+    align: u64,
+    mut data: R,
+    mut output: W,
+) -> io::Result<W> {
+    if is_64 {
+        // `Elf64_Chdr`
+        output.write_all(&endianness.write_u32_bytes(compression.ch_type()))?;
+        output.write_all(&endianness.write_u32_bytes(0))?;
+        output.write_all(&endianness.write_u64_bytes(size))?;
+        output.write_all(&endianness.write_u64_bytes(align))?;
+    } else {
+        // `Elf32_Chdr`
+        let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "section too large");
+        let size: u32 = size.try_into().map_err(too_large)?;
+        let align: u32 = align.try_into().map_err(too_large)?;
+        output.write_all(&endianness.write_u32_bytes(compression.ch_type()))?;
+        output.write_all(&endianness.write_u32_bytes(size))?;
+        output.write_all(&endianness.write_u32_bytes(align))?;
+    }
+
+    match compression {
+        OutputCompression::Zlib => {
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(output, flate2::Compression::default());
+            io::copy(&mut data, &mut encoder)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "zstd")]
+        OutputCompression::Zstd => {
+            let mut encoder = zstd::Encoder::new(output, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            io::copy(&mut data, &mut encoder)?;
+            encoder.finish()
+        }
+    }
+}
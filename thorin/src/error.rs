@@ -1,5 +1,8 @@
 use std::error::Error as StdError;
 use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{executable::ReferencedUnit, index::Bucketable};
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
@@ -15,6 +18,37 @@ impl<'a, T: StdError + 'a> AsDynError<'a> for T {
     }
 }
 
+/// Location in an input where an error occurred, see `Error::Input`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct InputLocation {
+    /// Path to the input object, package, archive or executable.
+    pub path: PathBuf,
+    /// Name of the member of an archive input, if the error occurred in an archive member.
+    pub member: Option<String>,
+    /// Name of the section, if the error occurred in a specific section.
+    pub section: Option<String>,
+    /// Offset in the section (e.g. of the unit being read), if it is known.
+    pub offset: Option<u64>,
+}
+
+impl fmt::Display for InputLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}", self.path.display())?;
+        if let Some(member) = &self.member {
+            write!(f, "({})", member)?;
+        }
+        write!(f, "`")?;
+        if let Some(section) = &self.section {
+            write!(f, ", section `{}`", section)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at offset 0x{:x}", offset)?;
+        }
+        Ok(())
+    }
+}
+
 /// Diagnostics (and contexts) emitted during DWARF packaging.
 #[derive(Debug)]
 #[non_exhaustive]
@@ -32,7 +66,7 @@ pub enum Error {
     ParseArchiveFile(object::Error),
     /// Failed to parse archive member.
     ParseArchiveMember(object::Error),
-    /// Invalid kind of input. Only archive and elf files are supported input files.
+    /// Invalid kind of input. Only archive, elf and mach-o files are supported input files.
     InvalidInputKind,
     /// Failed to decompress data. `thorin` uses `object` for decompression, so `object` probably
     /// didn't have support for the type of compression used.
@@ -56,6 +90,9 @@ pub enum Error {
     TopLevelDieNotUnit,
     /// Section name isn't UTF-8.
     NonUtf8SectionName(object::Error),
+    /// Truncated Mach-O section name is the name of both a DWARF object section and a non-split
+    /// DWARF section, in an object with non-split DWARF.
+    AmbiguousMachOSection,
     /// Section required of input DWARF objects was missing.
     MissingRequiredSection(&'static str),
     /// Failed to parse unit abbreviations.
@@ -92,12 +129,64 @@ pub enum Error {
     NotSplitUnit,
     /// Found duplicate split compilation unit.
     DuplicateUnit(u64),
-    /// Unit referenced by an executable was not found.
-    MissingReferencedUnit(u64),
+    /// Found duplicate split compilation unit which isn't identical to the unit with the same
+    /// `DwoId` already in the package.
+    MismatchedDuplicateUnit(u64),
+    /// Units referenced by executables were not found.
+    MissingReferencedUnits(Vec<ReferencedUnit>),
     /// No output object was created from inputs
     NoOutputObjectCreated,
     /// Input objects have different encodings.
     MixedInputEncodings,
+    /// Streaming output was requested for a DWARF package that isn't an ELF object.
+    UnsupportedStreamingOutputFormat,
+    /// Streaming output was requested for a DWARF package with an architecture that can't be
+    /// written.
+    UnsupportedStreamingArchitecture(object::Architecture),
+    /// Failed to create temporary file for a section of a streamed DWARF package.
+    CreateTemporaryFile(std::io::Error),
+    /// Failed to write to temporary file for a section of a streamed DWARF package.
+    WriteTemporaryFile(std::io::Error),
+    /// Failed to read from temporary file for a section of a streamed DWARF package.
+    ReadTemporaryFile(std::io::Error),
+    /// Sharded DWARF package can only be returned with `DwarfPackage::finish_shards` or
+    /// `DwarfPackage::finish_shards_to`.
+    ShardedOutputRequiresShards,
+    /// Failed to emit DWARF package object.
+    EmitOutputObject(object::write::Error),
+    /// Failed to write DWARF package to output.
+    WriteOutput(std::io::Error),
+    /// Contribution of a unit in a DWARF package's index isn't within its section.
+    InvalidContribution(&'static str, u64, u64),
+    /// Attribute of a unit in a DWARF package has a form that isn't a string.
+    UnsupportedStringForm(gimli::DwAt),
+    /// Compressed output is only supported for ELF DWARF packages.
+    UnsupportedCompressedOutputFormat,
+    /// Compressed output is not supported for the architecture of the DWARF package.
+    UnsupportedCompressedOutputArchitecture(object::Architecture),
+    /// Failed to compress a section of the DWARF package.
+    CompressSection(std::io::Error),
+    /// Build ID notes are only supported for ELF DWARF packages.
+    UnsupportedBuildIdNoteFormat,
+    /// Offset of a string in the merged `.debug_str.dwo` is too large for a DWARF32
+    /// `.debug_str_offsets.dwo` section.
+    StrOffsetTooLarge(u64),
+    /// DWARF32 `.debug_str_offsets.dwo` section of an input is too large to have a 32-bit unit
+    /// length.
+    StrOffsetsSectionTooLarge(u64),
+    /// DWARF 5 `.debug_str_offsets.dwo` section of an input is too small to contain its header.
+    StrOffsetsSectionTooSmall(u64),
+    /// Offset or size of a contribution is too large for the 32-bit offsets and sizes of a DWARF
+    /// package's index.
+    ContributionTooLarge(&'static str, u64),
+    /// Index contains more than one entry for the same unit.
+    DuplicateIndexEntry(u64),
+    /// Index has too many units for the 32-bit number of units in its header.
+    TooManyUnits(usize),
+    /// Section of a streamed DWARF package is too large to be written on this platform.
+    StreamingSectionTooLarge(u64),
+    /// Error occurred in an input added to the DWARF package, at the location given.
+    Input(InputLocation, Box<Error>),
 
     /// Catch-all for `std::io::Error`.
     Io(std::io::Error),
@@ -130,6 +219,7 @@ impl StdError for Error {
             Error::NoDie => None,
             Error::TopLevelDieNotUnit => None,
             Error::NonUtf8SectionName(source) => Some(source.as_dyn_error()),
+            Error::AmbiguousMachOSection => None,
             Error::MissingRequiredSection(_) => None,
             Error::ParseUnitAbbreviations(source) => Some(source.as_dyn_error()),
             Error::ParseUnitAttribute(source) => Some(source.as_dyn_error()),
@@ -147,9 +237,32 @@ impl StdError for Error {
             Error::MultipleDebugTypesSection => None,
             Error::NotSplitUnit => None,
             Error::DuplicateUnit(_) => None,
-            Error::MissingReferencedUnit(_) => None,
+            Error::MismatchedDuplicateUnit(_) => None,
+            Error::MissingReferencedUnits(_) => None,
             Error::NoOutputObjectCreated => None,
             Error::MixedInputEncodings => None,
+            Error::UnsupportedStreamingOutputFormat => None,
+            Error::UnsupportedStreamingArchitecture(_) => None,
+            Error::CreateTemporaryFile(source) => Some(source.as_dyn_error()),
+            Error::WriteTemporaryFile(source) => Some(source.as_dyn_error()),
+            Error::ReadTemporaryFile(source) => Some(source.as_dyn_error()),
+            Error::ShardedOutputRequiresShards => None,
+            Error::EmitOutputObject(source) => Some(source.as_dyn_error()),
+            Error::WriteOutput(source) => Some(source.as_dyn_error()),
+            Error::InvalidContribution(..) => None,
+            Error::UnsupportedStringForm(_) => None,
+            Error::UnsupportedCompressedOutputFormat => None,
+            Error::UnsupportedCompressedOutputArchitecture(_) => None,
+            Error::CompressSection(source) => Some(source.as_dyn_error()),
+            Error::UnsupportedBuildIdNoteFormat => None,
+            Error::StrOffsetTooLarge(_) => None,
+            Error::StrOffsetsSectionTooLarge(_) => None,
+            Error::StrOffsetsSectionTooSmall(_) => None,
+            Error::ContributionTooLarge(..) => None,
+            Error::DuplicateIndexEntry(_) => None,
+            Error::TooManyUnits(_) => None,
+            Error::StreamingSectionTooLarge(_) => None,
+            Error::Input(_, source) => Some(source.as_dyn_error()),
             Error::Io(transparent) => StdError::source(transparent.as_dyn_error()),
             Error::ObjectRead(transparent) => StdError::source(transparent.as_dyn_error()),
             Error::ObjectWrite(transparent) => StdError::source(transparent.as_dyn_error()),
@@ -167,7 +280,7 @@ impl fmt::Display for Error {
             Error::ParseObjectFile(_) => write!(f, "Failed to parse input object file"),
             Error::ParseArchiveFile(_) => write!(f, "Failed to parse input archive file"),
             Error::ParseArchiveMember(_) => write!(f, "Failed to parse archive member"),
-            Error::InvalidInputKind => write!(f, "Input is not an archive or elf object"),
+            Error::InvalidInputKind => write!(f, "Input is not an archive, elf or mach-o object"),
             Error::DecompressData(_) => write!(f, "Failed to decompress compressed section"),
             Error::NamelessSection(_, offset) => {
                 write!(f, "Section without name at offset 0x{:08x}", offset)
@@ -202,6 +315,11 @@ impl fmt::Display for Error {
             Error::NonUtf8SectionName(_) => {
                 write!(f, "Section name is not valid UTF-8")
             }
+            Error::AmbiguousMachOSection => write!(
+                f,
+                "Mach-O section could be a DWARF object section or a non-split DWARF section, as \
+                 the object also has non-split DWARF"
+            ),
             Error::MissingRequiredSection(section) => {
                 write!(f, "Input object missing required section `{}`", section)
             }
@@ -247,11 +365,103 @@ impl fmt::Display for Error {
             Error::DuplicateUnit(unit) => {
                 write!(f, "Duplicate split compilation unit (0x{:08x})", unit)
             }
-            Error::MissingReferencedUnit(unit) => {
-                write!(f, "Unit 0x{:08x} referenced by executable was not found", unit)
+            Error::MismatchedDuplicateUnit(unit) => write!(
+                f,
+                "Duplicate split compilation unit (0x{:08x}) differs from the unit in the package",
+                unit
+            ),
+            Error::MissingReferencedUnits(units) => {
+                write!(f, "{} unit(s) referenced by executables were not found:", units.len())?;
+                for unit in units {
+                    write!(
+                        f,
+                        "\n  unit 0x{:016x} (`{}`) referenced by `{}`",
+                        unit.id.index(),
+                        unit.path().display(),
+                        unit.executable.display()
+                    )?;
+                }
+                Ok(())
             }
             Error::NoOutputObjectCreated => write!(f, "No output object was created from inputs"),
             Error::MixedInputEncodings => write!(f, "Input objects haved mixed encodings"),
+            Error::UnsupportedStreamingOutputFormat => {
+                write!(f, "Streaming output is only supported for elf DWARF packages")
+            }
+            Error::UnsupportedStreamingArchitecture(arch) => {
+                write!(f, "Streaming output is not supported for architecture `{:?}`", arch)
+            }
+            Error::CreateTemporaryFile(_) => {
+                write!(f, "Failed to create temporary file for section of DWARF package")
+            }
+            Error::WriteTemporaryFile(_) => {
+                write!(f, "Failed to write temporary file for section of DWARF package")
+            }
+            Error::ReadTemporaryFile(_) => {
+                write!(f, "Failed to read temporary file for section of DWARF package")
+            }
+            Error::ShardedOutputRequiresShards => {
+                write!(f, "Sharded DWARF package must be finished with `finish_shards`")
+            }
+            Error::EmitOutputObject(_) => write!(f, "Failed to emit DWARF package object"),
+            Error::WriteOutput(_) => write!(f, "Failed to write DWARF package to output"),
+            Error::InvalidContribution(section, offset, size) => write!(
+                f,
+                "Contribution at offset 0x{:08x} with size 0x{:08x} is not within `{}` section",
+                offset, size, section
+            ),
+            Error::UnsupportedStringForm(attr) => {
+                write!(f, "Attribute `{}` of unit doesn't have a string form", attr)
+            }
+            Error::UnsupportedCompressedOutputFormat => {
+                write!(f, "Compressed output is only supported for elf DWARF packages")
+            }
+            Error::UnsupportedCompressedOutputArchitecture(arch) => {
+                write!(f, "Compressed output is not supported for architecture `{:?}`", arch)
+            }
+            Error::CompressSection(_) => write!(f, "Failed to compress section of DWARF package"),
+            Error::UnsupportedBuildIdNoteFormat => {
+                write!(f, "Build ID notes are only supported for elf DWARF packages")
+            }
+            Error::StrOffsetTooLarge(offset) => write!(
+                f,
+                "String at offset 0x{:x} in the DWARF package's `.debug_str.dwo` section can't \
+                 be referenced from a DWARF32 `.debug_str_offsets.dwo` section",
+                offset
+            ),
+            Error::StrOffsetsSectionTooLarge(size) => write!(
+                f,
+                "DWARF32 `.debug_str_offsets.dwo` section with size 0x{:x} is too large",
+                size
+            ),
+            Error::StrOffsetsSectionTooSmall(size) => write!(
+                f,
+                "DWARF 5 `.debug_str_offsets.dwo` section with size 0x{:x} is too small to \
+                 contain its header",
+                size
+            ),
+            Error::ContributionTooLarge(section, value) => write!(
+                f,
+                "Contribution to `{}` section with offset or size 0x{:x} is too large for the \
+                 32-bit offsets and sizes of a DWARF package index",
+                section, value
+            ),
+            Error::DuplicateIndexEntry(id) => {
+                write!(f, "Index has more than one entry for unit 0x{:016x}", id)
+            }
+            Error::TooManyUnits(count) => {
+                write!(
+                    f,
+                    "Index can't have {} units, which is more than 32-bit header allows",
+                    count
+                )
+            }
+            Error::StreamingSectionTooLarge(size) => write!(
+                f,
+                "Section of streamed DWARF package with size 0x{:x} is too large for this platform",
+                size
+            ),
+            Error::Input(location, _) => write!(f, "Error in {}", location),
             Error::Io(e) => fmt::Display::fmt(e, f),
             Error::ObjectRead(e) => fmt::Display::fmt(e, f),
             Error::ObjectWrite(e) => fmt::Display::fmt(e, f),
@@ -261,6 +471,87 @@ impl fmt::Display for Error {
     }
 }
 
+impl Error {
+    /// Returns the error which occurred, without the location in the input where it occurred.
+    pub fn without_location(&self) -> &Error {
+        match self {
+            Error::Input(_, source) => source.without_location(),
+            error => error,
+        }
+    }
+
+    /// Returns the location in the input where the error occurred, if it is known. Errors with the
+    /// DWARF package being created (such as an unsupported output format, or failing to write a
+    /// temporary file) never have a location, even if they occurred while adding an input.
+    pub fn location(&self) -> Option<&InputLocation> {
+        match self {
+            Error::Input(location, _) => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this error is with the DWARF package being created rather than with any
+    /// input.
+    fn is_output_error(&self) -> bool {
+        matches!(
+            self,
+            Error::UnsupportedStreamingOutputFormat
+                | Error::UnsupportedStreamingArchitecture(_)
+                | Error::CreateTemporaryFile(_)
+                | Error::WriteTemporaryFile(_)
+                | Error::ReadTemporaryFile(_)
+                | Error::StreamingSectionTooLarge(_)
+                | Error::UnsupportedCompressedOutputFormat
+                | Error::UnsupportedCompressedOutputArchitecture(_)
+                | Error::CompressSection(_)
+                | Error::UnsupportedBuildIdNoteFormat
+                | Error::EmitOutputObject(_)
+                | Error::WriteOutput(_)
+        )
+    }
+
+    /// Update the location of this error with `f`, adding a location if the error doesn't have
+    /// one (unless it is an error with the DWARF package being created).
+    fn update_location(self, f: impl FnOnce(&mut InputLocation)) -> Self {
+        let (mut location, source) = match self {
+            Error::Input(location, source) => (location, source),
+            error if error.is_output_error() => return error,
+            error => (InputLocation::default(), Box::new(error)),
+        };
+        f(&mut location);
+        Error::Input(location, source)
+    }
+
+    /// Record that this error occurred in `section` (at `offset`), unless it is already known to
+    /// have occurred in a section.
+    pub(crate) fn in_section(self, section: &str, offset: Option<u64>) -> Self {
+        self.update_location(|location| {
+            if location.section.is_none() {
+                location.section = Some(section.to_string());
+                location.offset = offset;
+            }
+        })
+    }
+
+    /// Record that this error occurred in archive member `member`, unless it is already known to
+    /// have occurred in an archive member.
+    pub(crate) fn in_member(self, member: &str) -> Self {
+        self.update_location(|location| {
+            location.member.get_or_insert_with(|| member.to_string());
+        })
+    }
+
+    /// Record that this error occurred in the input at `path`, unless it is already known to have
+    /// occurred in an input.
+    pub(crate) fn in_input(self, path: &Path) -> Self {
+        self.update_location(|location| {
+            if location.path.as_os_str().is_empty() {
+                location.path = path.to_path_buf();
+            }
+        })
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(source: std::io::Error) -> Self {
         Error::Io(source)
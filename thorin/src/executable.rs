@@ -0,0 +1,404 @@
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+};
+
+use gimli::{EndianSlice, Reader, UnitSectionOffset};
+use object::{FileKind, Object, ObjectSection};
+use tracing::{debug, trace};
+
+use crate::{
+    error::{Error, InputLocation, Result},
+    ext::EndianityExt,
+    index::Bucketable,
+    package::{dwo_identifier_of_unit, DwarfObject},
+    relocate::{add_relocations, Relocate, RelocationMap},
+    warning::Warning,
+    Session,
+};
+
+/// Directory searched for separate debug files of executables if no other directories are
+/// provided, see `DwarfPackage::with_debug_dir`.
+const DEFAULT_DEBUG_DIR: &str = "/usr/lib/debug";
+
+/// Split unit referenced by a skeleton unit in an executable.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReferencedUnit {
+    /// `DwoId` (or `DebugTypeSignature`) of the referenced split unit.
+    pub id: DwarfObject,
+    /// `DW_AT_dwo_name` (or `DW_AT_GNU_dwo_name`) of the skeleton unit.
+    pub dwo_name: String,
+    /// `DW_AT_comp_dir` of the skeleton unit, if it has one.
+    pub comp_dir: Option<String>,
+    /// Path to the executable containing the skeleton unit (or the archive containing the
+    /// relocatable object with the skeleton unit).
+    pub executable: PathBuf,
+}
+
+impl ReferencedUnit {
+    /// Returns the path to the DWARF object containing the referenced unit, the compilation
+    /// directory (if it exists) joined with the DWARF object name.
+    pub fn path(&self) -> PathBuf {
+        let mut path = match &self.comp_dir {
+            Some(comp_dir) => PathBuf::from(comp_dir),
+            None => PathBuf::new(),
+        };
+        path.push(&self.dwo_name);
+        path
+    }
+}
+
+/// Split units referenced by the skeleton units of an executable, and the executable's build ID.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ExecutableUnits {
+    /// Build ID of the executable (from its `NT_GNU_BUILD_ID` note), if it has one. Archives don't
+    /// have a build ID.
+    pub(crate) build_id: Option<Vec<u8>>,
+    /// Split units referenced by the skeleton units of the executable.
+    pub(crate) units: Vec<ReferencedUnit>,
+}
+
+/// Rules for finding the DWARF objects referenced by executables when they aren't at the path
+/// recorded in the executable (e.g. when the executable was built in a sandbox or on a remote
+/// executor).
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SearchPaths {
+    /// Prefixes of recorded paths and their replacements, in the order they were added.
+    remappings: Vec<(PathBuf, PathBuf)>,
+    /// Directories to look for DWARF objects in, in the order they were added.
+    dirs: Vec<PathBuf>,
+}
+
+impl SearchPaths {
+    /// Replace the prefix `from` of recorded paths with `to`.
+    pub(crate) fn add_remapping(&mut self, from: PathBuf, to: PathBuf) {
+        self.remappings.push((from, to));
+    }
+
+    /// Look for DWARF objects in `dir`.
+    pub(crate) fn add_dir(&mut self, dir: PathBuf) {
+        self.dirs.push(dir);
+    }
+
+    /// Returns `path` with the prefix of the last matching remapping replaced, or `path` if no
+    /// remapping matches (the same as `-fdebug-prefix-map`).
+    fn remap(&self, path: &Path) -> PathBuf {
+        self.remappings
+            .iter()
+            .rev()
+            .find_map(|(from, to)| path.strip_prefix(from).ok().map(|rest| to.join(rest)))
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    /// Returns the paths which the DWARF object containing `unit` could be found at, in the
+    /// order they should be tried: the (remapped) recorded path, then each search directory, then
+    /// the directory containing the executable.
+    ///
+    /// In each directory, the DWARF object name is tried as-is (if it is relative) and then just
+    /// its file name.
+    pub(crate) fn candidates(&self, unit: &ReferencedUnit) -> Vec<PathBuf> {
+        let dwo_name = Path::new(&unit.dwo_name);
+        let executable_dir = unit.executable.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut candidates = vec![self.remap(&unit.path())];
+        for dir in self.dirs.iter().map(PathBuf::as_path).chain(std::iter::once(executable_dir)) {
+            if dwo_name.is_relative() {
+                candidates.push(dir.join(dwo_name));
+            }
+            if let Some(file_name) = dwo_name.file_name() {
+                candidates.push(dir.join(file_name));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        candidates.retain(|candidate| seen.insert(candidate.clone()));
+        candidates
+    }
+}
+
+/// Returns the split units referenced by the skeleton units of the executable at `path` and its
+/// build ID, or the split units referenced by each object in the archive at `path` (e.g. a static
+/// library of relocatable objects).
+///
+/// Skeleton units of stripped executables are read from their separate debug file, which is
+/// found in `debug_dirs` (or `/usr/lib/debug`, if `debug_dirs` is empty), see
+/// `separate_debug_file`.
+#[tracing::instrument(level = "trace", skip(sess))]
+pub(crate) fn referenced_units<'session, Sess>(
+    sess: &'session Sess,
+    path: &Path,
+    debug_dirs: &[PathBuf],
+) -> Result<ExecutableUnits>
+where
+    Sess: Session<RelocationMap>,
+{
+    let data = sess.read_input(path).map_err(Error::ReadInput)?;
+    if FileKind::parse(data).map_err(Error::ParseFileKind)? != FileKind::Archive {
+        let obj = object::File::parse(data).map_err(Error::ParseObjectFile)?;
+        let build_id = obj.build_id().map_err(Error::ParseObjectFile)?.map(<[u8]>::to_vec);
+        let units = object_referenced_units(sess, &obj, path, None, debug_dirs)?;
+        return Ok(ExecutableUnits { build_id, units });
+    }
+
+    let archive =
+        object::read::archive::ArchiveFile::parse(data).map_err(Error::ParseArchiveFile)?;
+    let mut referenced_units = Vec::new();
+    for member in archive.members() {
+        let member = member.map_err(Error::ParseArchiveMember)?;
+        let name = String::from_utf8_lossy(member.name());
+        let data = member.data(data).map_err(|e| Error::from(e).in_member(&name))?;
+
+        match FileKind::parse(data) {
+            Ok(FileKind::Elf32 | FileKind::Elf64 | FileKind::MachO32 | FileKind::MachO64) => {
+                let obj = object::File::parse(data)
+                    .map_err(|e| Error::ParseObjectFile(e).in_member(&name))?;
+                let units = object_referenced_units(sess, &obj, path, Some(&name), debug_dirs)
+                    .map_err(|e| e.in_member(&name))?;
+                referenced_units.extend(units);
+            }
+            _ => {
+                trace!("skipping non-object archive member");
+                let location = InputLocation {
+                    path: path.to_path_buf(),
+                    member: Some(name.to_string()),
+                    ..Default::default()
+                };
+                sess.warn(Warning::SkippedArchiveMember(location));
+            }
+        }
+    }
+
+    Ok(ExecutableUnits { build_id: None, units: referenced_units })
+}
+
+/// Returns the split units referenced by the skeleton units of `obj`, which is the executable at
+/// `path` (or the archive member `member` of the archive at `path`), or by the skeleton units of
+/// its separate debug file if it doesn't have a `.debug_info` section.
+fn object_referenced_units<'session, Sess>(
+    sess: &'session Sess,
+    obj: &object::File<'session>,
+    path: &Path,
+    member: Option<&str>,
+    debug_dirs: &[PathBuf],
+) -> Result<Vec<ReferencedUnit>>
+where
+    Sess: Session<RelocationMap>,
+{
+    if obj.section_by_name(".debug_info").is_none() {
+        let debug_file = separate_debug_file(sess, obj, path, member, debug_dirs)?;
+        if let Some((debug_path, debug_obj)) = debug_file {
+            debug!(?debug_path, "reading skeleton units from separate debug file");
+            return skeleton_referenced_units(sess, &debug_obj, path)
+                .map_err(|e| e.in_input(&debug_path));
+        }
+    }
+
+    skeleton_referenced_units(sess, obj, path)
+}
+
+/// What a candidate separate debug file must match to be the separate debug file of an
+/// executable.
+#[derive(Clone, Copy, Debug)]
+enum DebugFileLink<'data> {
+    /// Build ID of the executable, which the separate debug file has too.
+    BuildId(&'data [u8]),
+    /// CRC-32 of the separate debug file from the executable's `.gnu_debuglink`.
+    Crc(u32),
+}
+
+/// Returns the path and contents of the separate debug file of the stripped executable `obj` at
+/// `path` (or in archive member `member`), or `None` if it doesn't reference a separate debug file
+/// or it couldn't be found (both of which are reported as warnings).
+///
+/// Separate debug files are found using the same conventions as GDB: by the executable's build ID
+/// at `<debug dir>/.build-id/xx/yyyy.debug`, then by the file name in the executable's
+/// `.gnu_debuglink` in the executable's directory, its `.debug` subdirectory and at
+/// `<debug dir>/<executable's directory>`. Debug directories are `debug_dirs` (or
+/// `/usr/lib/debug`, if `debug_dirs` is empty).
+fn separate_debug_file<'session, Sess>(
+    sess: &'session Sess,
+    obj: &object::File<'session>,
+    path: &Path,
+    member: Option<&str>,
+    debug_dirs: &[PathBuf],
+) -> Result<Option<(PathBuf, object::File<'session>)>>
+where
+    Sess: Session<RelocationMap>,
+{
+    let default_debug_dirs = [PathBuf::from(DEFAULT_DEBUG_DIR)];
+    let debug_dirs = if debug_dirs.is_empty() { &default_debug_dirs[..] } else { debug_dirs };
+
+    let mut candidates = Vec::new();
+    if let Some(build_id) = obj.build_id().map_err(Error::ParseObjectFile)? {
+        // Build IDs are split after their first byte, so can't be shorter than two bytes.
+        if build_id.len() >= 2 {
+            let hex: String = build_id.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let (dir, file) = hex.split_at(2);
+            for debug_dir in debug_dirs {
+                let candidate =
+                    debug_dir.join(".build-id").join(dir).join(format!("{}.debug", file));
+                candidates.push((candidate, DebugFileLink::BuildId(build_id)));
+            }
+        }
+    }
+    if let Some((name, crc)) = obj.gnu_debuglink().map_err(Error::ParseObjectFile)? {
+        let name = String::from_utf8_lossy(name);
+        let executable_dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        candidates.push((executable_dir.join(&*name), DebugFileLink::Crc(crc)));
+        candidates.push((executable_dir.join(".debug").join(&*name), DebugFileLink::Crc(crc)));
+        if let Ok(absolute_dir) = executable_dir.canonicalize() {
+            let relative_dir: PathBuf = absolute_dir
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .collect();
+            for debug_dir in debug_dirs {
+                let candidate = debug_dir.join(&relative_dir).join(&*name);
+                candidates.push((candidate, DebugFileLink::Crc(crc)));
+            }
+        }
+    }
+
+    if candidates.is_empty() {
+        let location = InputLocation {
+            path: path.to_path_buf(),
+            member: member.map(str::to_string),
+            ..Default::default()
+        };
+        sess.warn(Warning::NoDebugInformation(location));
+        return Ok(None);
+    }
+
+    for (candidate, link) in candidates {
+        // `.gnu_debuglink` can name the executable itself, which can't be its own debug file.
+        if candidate == path {
+            continue;
+        }
+
+        let data = match sess.read_input(&candidate) {
+            Ok(data) => data,
+            Err(_) => {
+                trace!(?candidate, "not found");
+                continue;
+            }
+        };
+        let debug_obj = match object::File::parse(data) {
+            Ok(debug_obj) => debug_obj,
+            Err(_) => {
+                debug!(?candidate, "not an object file");
+                continue;
+            }
+        };
+        let matches = match link {
+            DebugFileLink::BuildId(build_id) => {
+                matches!(debug_obj.build_id(), Ok(Some(other)) if other == build_id)
+            }
+            DebugFileLink::Crc(crc) => {
+                let mut hasher = crc32fast::Hasher::new();
+                hasher.update(data);
+                hasher.finalize() == crc
+            }
+        };
+        if matches {
+            return Ok(Some((candidate, debug_obj)));
+        }
+        debug!(?candidate, ?link, "separate debug file doesn't match executable");
+    }
+
+    sess.warn(Warning::MissingSeparateDebugFile(path.to_path_buf()));
+    Ok(None)
+}
+
+/// Returns the split units referenced by the skeleton units of `obj`, which is the executable at
+/// `path` (or a member of the archive at `path`, or the separate debug file of the executable).
+fn skeleton_referenced_units<'session, Sess>(
+    sess: &'session Sess,
+    obj: &object::File<'session>,
+    path: &Path,
+) -> Result<Vec<ReferencedUnit>>
+where
+    Sess: Session<RelocationMap>,
+{
+    let mut load_section = |id: gimli::SectionId| -> Result<_> {
+        let mut relocations = RelocationMap::default();
+        let data = match obj.section_by_name(id.name()) {
+            Some(ref section) => {
+                let in_section = |e: Error| e.in_section(id.name(), None);
+                add_relocations(&mut relocations, obj, section).map_err(in_section)?;
+                section
+                    .compressed_data()
+                    .and_then(|data| data.decompress())
+                    .map_err(|e| in_section(e.into()))?
+            }
+            // Use a non-zero capacity so that `ReaderOffsetId`s are unique.
+            None => Cow::Owned(Vec::with_capacity(1)),
+        };
+
+        let data_ref = sess.alloc_owned_cow(data);
+        let reader = EndianSlice::new(data_ref, obj.endianness().as_runtime_endian());
+        let section = reader;
+        let relocations = sess.alloc_relocation(relocations);
+        Ok(Relocate { relocations, section, reader })
+    };
+
+    let dwarf = gimli::Dwarf::load(&mut load_section)?;
+
+    let mut referenced_units = Vec::new();
+    let mut iter = dwarf.units();
+    while let Some(header) =
+        iter.next().map_err(|e| Error::ParseUnitHeader(e).in_section(".debug_info", None))?
+    {
+        let offset = match header.offset() {
+            UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+            UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+        };
+        let in_unit = |e: Error| e.in_section(".debug_info", Some(offset as u64));
+
+        let unit = dwarf.unit(header).map_err(|e| in_unit(Error::ParseUnit(e)))?;
+
+        let id = match dwo_identifier_of_unit(&dwarf.debug_abbrev, &unit.header).map_err(in_unit)? {
+            Some(id) => id,
+            None => {
+                debug!("no target");
+                continue;
+            }
+        };
+
+        let read_names = || -> Result<_> {
+            let mut cursor = unit.header.entries(&unit.abbreviations);
+            cursor.next_dfs()?;
+            let root = cursor.current().ok_or(Error::NoDie)?;
+
+            let dwo_name = if let Some(val) = root.attr_value(gimli::DW_AT_dwo_name)? {
+                // DWARF 5
+                val
+            } else if let Some(val) = root.attr_value(gimli::DW_AT_GNU_dwo_name)? {
+                // GNU Extension
+                val
+            } else {
+                return Err(Error::MissingDwoName(id.index()));
+            };
+            let dwo_name = dwarf.attr_string(&unit, dwo_name)?.to_string()?.into_owned();
+
+            let comp_dir = match &unit.comp_dir {
+                Some(comp_dir) => Some(comp_dir.to_string()?.into_owned()),
+                None => None,
+            };
+
+            Ok((dwo_name, comp_dir))
+        };
+        let (dwo_name, comp_dir) = read_names().map_err(in_unit)?;
+
+        referenced_units.push(ReferencedUnit {
+            id,
+            dwo_name,
+            comp_dir,
+            executable: path.to_path_buf(),
+        });
+    }
+
+    Ok(referenced_units)
+}
@@ -1,7 +1,7 @@
-use gimli::{Encoding, EndianSlice, RunTimeEndian, UnitIndex};
-use object::{Endianness, ObjectSection};
+use gimli::{Encoding, EndianSlice, RunTimeEndian, SectionId, UnitIndex};
+use object::{BinaryFormat, Endianness, Object, ObjectSection};
 
-use crate::{relocate::RelocationMap, Session};
+use crate::error::{Error, Result};
 
 /// Helper trait to translate between `object`'s `Endianness` and `gimli`'s `RunTimeEndian`.
 pub(crate) trait EndianityExt {
@@ -17,45 +17,95 @@ impl EndianityExt for Endianness {
     }
 }
 
-/// Helper trait to add `compressed_data_range` function to `ObjectSection` types.
-pub(crate) trait CompressedDataRangeExt<'input, 'session: 'input>:
-    ObjectSection<'input>
-{
-    /// Return the decompressed contents of the section data in the given range.
-    fn compressed_data_range(
-        &self,
-        sess: &'session impl Session<RelocationMap>,
-        address: u64,
-        size: u64,
-    ) -> object::Result<Option<&'input [u8]>>;
+/// Sections which can be found in DWARF objects and packages, and their names in ELF objects.
+///
+/// `gimli::SectionId::dwo_name` doesn't have a name for `.debug_macinfo.dwo`.
+const DWO_SECTIONS: [(SectionId, &str); 13] = [
+    (SectionId::DebugAbbrev, ".debug_abbrev.dwo"),
+    (SectionId::DebugCuIndex, ".debug_cu_index"),
+    (SectionId::DebugInfo, ".debug_info.dwo"),
+    (SectionId::DebugLine, ".debug_line.dwo"),
+    (SectionId::DebugLoc, ".debug_loc.dwo"),
+    (SectionId::DebugLocLists, ".debug_loclists.dwo"),
+    (SectionId::DebugMacinfo, ".debug_macinfo.dwo"),
+    (SectionId::DebugMacro, ".debug_macro.dwo"),
+    (SectionId::DebugRngLists, ".debug_rnglists.dwo"),
+    (SectionId::DebugStr, ".debug_str.dwo"),
+    (SectionId::DebugStrOffsets, ".debug_str_offsets.dwo"),
+    (SectionId::DebugTuIndex, ".debug_tu_index"),
+    (SectionId::DebugTypes, ".debug_types.dwo"),
+];
+
+/// Returns the name of the Mach-O section corresponding to the ELF section named `elf_name`,
+/// which replaces the `.` prefix with `__` and is limited to sixteen bytes (e.g.
+/// `.debug_str_offsets.dwo` is `__debug_str_offs`).
+pub(crate) fn macho_section_name(elf_name: &str) -> String {
+    format!("__{}", &elf_name[1..elf_name.len().min(15)])
 }
 
-impl<'input, 'session: 'input, S> CompressedDataRangeExt<'input, 'session> for S
-where
-    S: ObjectSection<'input>,
-{
-    fn compressed_data_range(
-        &self,
-        sess: &'session impl Session<RelocationMap>,
-        address: u64,
-        size: u64,
-    ) -> object::Result<Option<&'input [u8]>> {
-        let data = self.compressed_data()?.decompress()?;
-
-        /// Originally from `object::read::util`, used in `ObjectSection::data_range`, but not
-        /// public.
-        fn data_range(
-            data: &[u8],
-            data_address: u64,
-            range_address: u64,
-            size: u64,
-        ) -> Option<&[u8]> {
-            let offset = range_address.checked_sub(data_address)?;
-            data.get(offset.try_into().ok()?..)?.get(..size.try_into().ok()?)
+/// Returns `true` if the Mach-O name of the ELF section named `elf_name` is also the Mach-O name
+/// of the corresponding non-split DWARF section once truncated (e.g. `.debug_str_offsets.dwo` and
+/// `.debug_str_offsets` are both `__debug_str_offs`).
+fn is_ambiguous_macho_section_name(elf_name: &str) -> bool {
+    match elf_name.strip_suffix(".dwo") {
+        Some(non_split_name) => macho_section_name(elf_name) == macho_section_name(non_split_name),
+        None => false,
+    }
+}
+
+/// Helper trait to identify the sections of DWARF objects and packages regardless of the object
+/// file format they were found in.
+pub(crate) trait DwoSectionIdExt: Sized {
+    /// Returns the `SectionId` of the DWARF object section `section` of `obj`.
+    ///
+    /// ELF sections are named `.debug_info.dwo` (or `.zdebug_info.dwo` when compressed with the
+    /// GNU extension). Mach-O sections are in the `__DWARF` segment and named `__debug_info.dwo`,
+    /// truncated to sixteen bytes (e.g. `__debug_abbrev.d`). Truncation gives
+    /// `.debug_loclists.dwo`, `.debug_rnglists.dwo` and `.debug_str_offsets.dwo` the same names
+    /// as the non-split DWARF sections, so these are only identified in Mach-O objects without a
+    /// non-split `__debug_info` section, and are an `Error::AmbiguousMachOSection` otherwise.
+    fn from_dwo_section<'data, 'file>(
+        obj: &'file object::File<'data>,
+        section: &object::Section<'data, 'file>,
+    ) -> Result<Option<Self>>;
+
+    /// Returns the name of the section in ELF DWARF objects and packages (unlike
+    /// `gimli::SectionId::dwo_name`, this includes `.debug_macinfo.dwo`).
+    fn dwo_section_name(&self) -> Option<&'static str>;
+}
+
+impl DwoSectionIdExt for SectionId {
+    fn from_dwo_section<'data, 'file>(
+        obj: &'file object::File<'data>,
+        section: &object::Section<'data, 'file>,
+    ) -> Result<Option<Self>> {
+        let name = section.name().map_err(Error::NonUtf8SectionName)?;
+        if obj.format() != BinaryFormat::MachO {
+            return Ok(DWO_SECTIONS
+                .iter()
+                .find(|(_, elf_name)| {
+                    name == *elf_name || name.strip_prefix(".z") == elf_name.strip_prefix('.')
+                })
+                .map(|(id, _)| *id));
+        }
+
+        if section.segment_name()? != Some("__DWARF") {
+            return Ok(None);
+        }
+        match DWO_SECTIONS.iter().find(|(_, elf_name)| name == macho_section_name(elf_name)) {
+            Some((_, elf_name))
+                if is_ambiguous_macho_section_name(elf_name)
+                    && obj.section_by_name("__debug_info").is_some() =>
+            {
+                Err(Error::AmbiguousMachOSection.in_section(name, None))
+            }
+            Some((id, _)) => Ok(Some(*id)),
+            None => Ok(None),
         }
+    }
 
-        let data_ref = sess.alloc_owned_cow(data);
-        Ok(data_range(data_ref, self.address(), address, size))
+    fn dwo_section_name(&self) -> Option<&'static str> {
+        DWO_SECTIONS.iter().find(|(id, _)| id == self).map(|(_, elf_name)| *elf_name)
     }
 }
 
@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+use crate::{
+    error::{Error, Result},
+    package::{DebugTypeSignature, DwoId, UnitSections},
+};
+
+/// Selects the units of inputs which are added to a DWARF package, see
+/// `DwarfPackage::with_unit_filter`.
+///
+/// Compilation units are selected if their `DwoId` was added to the filter or if their
+/// `DW_AT_name` matches one of the filter's name patterns. Type units are selected if they are
+/// referenced by a selected unit.
+#[derive(Clone, Debug, Default)]
+pub struct UnitFilter {
+    dwo_ids: HashSet<DwoId>,
+    name_patterns: Vec<String>,
+}
+
+impl UnitFilter {
+    /// Create a new `UnitFilter` which doesn't select any compilation units.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Select the compilation unit with `DwoId` `dwo_id`.
+    pub fn with_dwo_id(mut self, dwo_id: DwoId) -> Self {
+        self.add_dwo_id(dwo_id);
+        self
+    }
+
+    /// Select compilation units whose `DW_AT_name` matches `pattern`, where `*` matches any
+    /// sequence of characters and `?` matches any single character.
+    pub fn with_name_pattern(mut self, pattern: String) -> Self {
+        self.name_patterns.push(pattern);
+        self
+    }
+
+    /// Select the compilation unit with `DwoId` `dwo_id`.
+    pub(crate) fn add_dwo_id(&mut self, dwo_id: DwoId) {
+        self.dwo_ids.insert(dwo_id);
+    }
+
+    /// Returns `true` if the compilation unit with `DwoId` `dwo_id` and `DW_AT_name` `name` is
+    /// selected.
+    pub(crate) fn selects(&self, dwo_id: DwoId, name: Option<&[u8]>) -> bool {
+        self.dwo_ids.contains(&dwo_id)
+            || matches!(name, Some(name) if self
+                .name_patterns
+                .iter()
+                .any(|pattern| glob_matches(pattern.as_bytes(), name)))
+    }
+}
+
+/// Returns `true` if `name` matches the glob `pattern`, where `*` matches any sequence of bytes
+/// and `?` matches any single byte.
+fn glob_matches(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` in the pattern and of the byte of the name that it is currently
+    // matched up to, so that the `*` can match more of the name if the rest of the pattern fails.
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Returns the `DW_AT_name` of the root entry of the unit in `data` (from a `.debug_types.dwo`
+/// section if `is_debug_types`), if it has one.
+pub(crate) fn unit_name<'input>(
+    data: &'input [u8],
+    is_debug_types: bool,
+    sections: &UnitSections<'input>,
+) -> Result<Option<&'input [u8]>> {
+    let header = sections.read_header(data, is_debug_types)?;
+    let abbreviations =
+        header.abbreviations(&sections.debug_abbrev()).map_err(Error::ParseUnitAbbreviations)?;
+    let mut cursor = header.entries(&abbreviations);
+    cursor.next_dfs()?;
+    let root = cursor.current().ok_or(Error::NoDie)?;
+    match root.attr_value(gimli::DW_AT_name).map_err(Error::ParseUnitAttribute)? {
+        Some(value) => sections.string(value),
+        None => Ok(None),
+    }
+}
+
+/// Returns the signatures of the type units referenced (with `DW_FORM_ref_sig8`) by the
+/// debugging information entries of the unit in `data` (from a `.debug_types.dwo` section if
+/// `is_debug_types`).
+pub(crate) fn referenced_type_units(
+    data: &[u8],
+    is_debug_types: bool,
+    sections: &UnitSections<'_>,
+) -> Result<Vec<DebugTypeSignature>> {
+    let header = sections.read_header(data, is_debug_types)?;
+    let abbreviations =
+        header.abbreviations(&sections.debug_abbrev()).map_err(Error::ParseUnitAbbreviations)?;
+
+    let mut signatures = Vec::new();
+    let mut cursor = header.entries(&abbreviations);
+    while let Some((_, entry)) = cursor.next_dfs()? {
+        let mut attrs = entry.attrs();
+        while let Some(attr) = attrs.next().map_err(Error::ParseUnitAttribute)? {
+            if let gimli::AttributeValue::DebugTypesRef(signature) = attr.value() {
+                signatures.push(signature.into());
+            }
+        }
+    }
+
+    Ok(signatures)
+}
@@ -19,10 +19,14 @@ pub(crate) trait Bucketable {
 
 /// Returns a hash table computed for `elements`. Used in the `.debug_{cu,tu}_index` sections.
 #[tracing::instrument(level = "trace", skip_all)]
-fn bucket<B: Bucketable + fmt::Debug>(elements: &[B]) -> Vec<u32> {
-    let unit_count: u32 = elements.len().try_into().expect("unit count larger than u32");
+fn bucket<B: Bucketable + fmt::Debug>(elements: &[B]) -> Result<Vec<u32>> {
+    let unit_count = elements.len() as u64;
     let num_buckets = if elements.len() < 2 { 2 } else { (3 * unit_count / 2).next_power_of_two() };
-    let mask: u64 = num_buckets as u64 - 1;
+    // Both the number of units and the number of buckets are written as 32-bit values.
+    if u32::try_from(num_buckets).is_err() {
+        return Err(Error::TooManyUnits(elements.len()));
+    }
+    let mask: u64 = num_buckets - 1;
     trace!(?mask);
 
     let mut buckets = vec![0u32; num_buckets as usize];
@@ -35,7 +39,9 @@ fn bucket<B: Bucketable + fmt::Debug>(elements: &[B]) -> Vec<u32> {
         trace!(?s, ?h, ?hp);
 
         while buckets[h as usize] > 0 {
-            assert!(elements[(buckets[h as usize] - 1) as usize].index() != elem.index());
+            if elements[(buckets[h as usize] - 1) as usize].index() == elem.index() {
+                return Err(Error::DuplicateIndexEntry(s));
+            }
             h = (h + hp) & mask;
             trace!(?h);
         }
@@ -44,7 +50,7 @@ fn bucket<B: Bucketable + fmt::Debug>(elements: &[B]) -> Vec<u32> {
         trace!(?buckets);
     }
 
-    buckets
+    Ok(buckets)
 }
 
 /// New-type'd offset into a section of a compilation/type unit's contribution.
@@ -104,18 +110,36 @@ impl IndexEntry {
             + self.debug_macro.map_or(0, |_| 1)
     }
 
-    /// Returns `true` if the header of this entry is the same as the header of `other`.
-    fn eq_header(&self, other: &Self) -> bool {
-        (self.debug_info.is_some() == other.debug_info.is_some())
-            && (self.debug_types.is_some() == other.debug_types.is_some())
-            && (self.debug_abbrev.is_some() == other.debug_abbrev.is_some())
-            && (self.debug_line.is_some() == other.debug_line.is_some())
-            && (self.debug_loc.is_some() == other.debug_loc.is_some())
-            && (self.debug_loclists.is_some() == other.debug_loclists.is_some())
-            && (self.debug_rnglists.is_some() == other.debug_rnglists.is_some())
-            && (self.debug_str_offsets.is_some() == other.debug_str_offsets.is_some())
-            && (self.debug_macinfo.is_some() == other.debug_macinfo.is_some())
-            && (self.debug_macro.is_some() == other.debug_macro.is_some())
+    /// Returns this entry with an empty contribution in every column that `columns` has a
+    /// contribution in and this entry doesn't. Empty contributions are at the end of the
+    /// contribution of `previous` in the same column, or at the start of the section.
+    fn with_columns_of(self, columns: &Self, previous: Option<&Self>) -> Self {
+        macro_rules! fill {
+            ($column:ident) => {
+                self.$column.or_else(|| {
+                    columns.$column.map(|_| {
+                        let end = previous
+                            .and_then(|previous| previous.$column)
+                            .map_or(0, |previous| previous.offset.0 + previous.size);
+                        Contribution { offset: ContributionOffset(end), size: 0 }
+                    })
+                })
+            };
+        }
+
+        Self {
+            debug_info: fill!(debug_info),
+            debug_types: fill!(debug_types),
+            debug_abbrev: fill!(debug_abbrev),
+            debug_line: fill!(debug_line),
+            debug_loc: fill!(debug_loc),
+            debug_loclists: fill!(debug_loclists),
+            debug_rnglists: fill!(debug_rnglists),
+            debug_str_offsets: fill!(debug_str_offsets),
+            debug_macinfo: fill!(debug_macinfo),
+            debug_macro: fill!(debug_macro),
+            ..self
+        }
     }
 
     /// Write the header row for this entry.
@@ -185,37 +209,29 @@ impl IndexEntry {
     ) -> Result<()>
     where
         Endian: gimli::Endianity,
-        Proj: Fn(Contribution) -> u32,
+        Proj: Fn(Contribution) -> u64,
     {
-        if let Some(debug_info) = self.debug_info {
-            out.write_u32(proj(debug_info))?;
-        }
-        if let Some(debug_types) = self.debug_types {
-            out.write_u32(proj(debug_types))?;
-        }
-        if let Some(debug_abbrev) = self.debug_abbrev {
-            out.write_u32(proj(debug_abbrev))?;
-        }
-        if let Some(debug_line) = self.debug_line {
-            out.write_u32(proj(debug_line))?;
-        }
-        if let Some(debug_loc) = self.debug_loc {
-            out.write_u32(proj(debug_loc))?;
-        }
-        if let Some(debug_loclists) = self.debug_loclists {
-            out.write_u32(proj(debug_loclists))?;
-        }
-        if let Some(debug_rnglists) = self.debug_rnglists {
-            out.write_u32(proj(debug_rnglists))?;
-        }
-        if let Some(debug_str_offsets) = self.debug_str_offsets {
-            out.write_u32(proj(debug_str_offsets))?;
-        }
-        if let Some(debug_macinfo) = self.debug_macinfo {
-            out.write_u32(proj(debug_macinfo))?;
-        }
-        if let Some(debug_macro) = self.debug_macro {
-            out.write_u32(proj(debug_macro))?;
+        // Same order as the columns written by `write_header`.
+        let contributions = [
+            (".debug_info.dwo", self.debug_info),
+            (".debug_types.dwo", self.debug_types),
+            (".debug_abbrev.dwo", self.debug_abbrev),
+            (".debug_line.dwo", self.debug_line),
+            (".debug_loc.dwo", self.debug_loc),
+            (".debug_loclists.dwo", self.debug_loclists),
+            (".debug_rnglists.dwo", self.debug_rnglists),
+            (".debug_str_offsets.dwo", self.debug_str_offsets),
+            (".debug_macinfo.dwo", self.debug_macinfo),
+            (".debug_macro.dwo", self.debug_macro),
+        ];
+        for (section, contribution) in contributions {
+            if let Some(contribution) = contribution {
+                // Offsets and sizes in the index are always 32-bit, even for DWARF64 units.
+                let value = proj(contribution);
+                let value =
+                    value.try_into().map_err(|_| Error::ContributionTooLarge(section, value))?;
+                out.write_u32(value)?;
+            }
         }
 
         Ok(())
@@ -236,11 +252,11 @@ pub(crate) fn write_index<'output, Endian: gimli::Endianity>(
 ) -> Result<EndianVec<Endian>> {
     let mut out = EndianVec::new(endianness);
 
-    if entries.len() == 0 {
+    if entries.is_empty() {
         return Ok(out);
     }
 
-    let buckets = bucket(entries);
+    let buckets = bucket(entries)?;
     debug!(?buckets);
 
     let encoding = entries[0].encoding;
@@ -249,18 +265,23 @@ pub(crate) fn write_index<'output, Endian: gimli::Endianity>(
     }
     debug!(?encoding);
 
+    // Every entry has the same columns, units which don't contribute to a section have an empty
+    // contribution to it after the contribution of the previous unit (the same as `llvm-dwp`).
+    let columns =
+        entries.iter().fold(entries[0], |columns, entry| columns.with_columns_of(entry, None));
+    let mut previous = None;
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let entry = entry.with_columns_of(&columns, previous.as_ref());
+            previous = Some(entry);
+            entry
+        })
+        .collect();
+
     let num_columns = entries[0].number_of_columns();
     debug!(?entries, ?num_columns);
 
-    assert!(entries.windows(2).all(|window| {
-        trace!(?window);
-        match window {
-            [_] => true,
-            [first, second] => first.eq_header(second),
-            _ => panic!("invalid window"),
-        }
-    }));
-
     // Write header..
     if encoding.is_gnu_extension_dwarf_package_format() {
         // GNU Extension
@@ -274,10 +295,10 @@ pub(crate) fn write_index<'output, Endian: gimli::Endianity>(
 
     // Columns (e.g. info, abbrev, loc, etc.)
     out.write_u32(num_columns)?;
-    // Number of units
-    out.write_u32(entries.len().try_into().expect("number of units larger than u32"))?;
+    // Number of units (`bucket` checked that this and the number of buckets fit in 32 bits)
+    out.write_u32(entries.len() as u32)?;
     // Number of buckets
-    out.write_u32(buckets.len().try_into().expect("number of buckets larger than u32"))?;
+    out.write_u32(buckets.len() as u32)?;
 
     // Write signatures..
     for i in &buckets {
@@ -297,18 +318,13 @@ pub(crate) fn write_index<'output, Endian: gimli::Endianity>(
     entries[0].write_header(&mut out)?;
 
     // Write offsets..
-    let write_offset = |contrib: Contribution| {
-        contrib.offset.0.try_into().expect("contribution offset larger than u32")
-    };
-    for entry in entries {
-        entry.write_contribution(&mut out, write_offset)?;
+    for entry in &entries {
+        entry.write_contribution(&mut out, |contrib| contrib.offset.0)?;
     }
 
     // Write sizes..
-    let write_size =
-        |contrib: Contribution| contrib.size.try_into().expect("contribution size larger than u32");
-    for entry in entries {
-        entry.write_contribution(&mut out, write_size)?;
+    for entry in &entries {
+        entry.write_contribution(&mut out, |contrib| contrib.size)?;
     }
 
     Ok(out)
@@ -1,33 +1,55 @@
 use std::{
     borrow::Cow,
     collections::HashSet,
-    fmt,
+    fmt, io,
     path::{Path, PathBuf},
 };
 
-use gimli::{EndianSlice, Reader};
-use object::{write::Object as WritableObject, FileKind, Object, ObjectSection};
+use indexmap::IndexMap;
+use object::{write::Object as WritableObject, BinaryFormat, FileKind};
 use tracing::{debug, trace};
 
 use crate::{
     error::Result,
-    ext::EndianityExt,
-    index::Bucketable,
-    package::{dwo_identifier_of_unit, DwarfObject, InProgressDwarfPackage},
-    relocate::{add_relocations, Relocate, RelocationMap},
+    executable::{referenced_units, SearchPaths},
+    ext::macho_section_name,
+    package::{InProgressDwarfPackage, OutputObject, PreparedContents, PreparedInput},
 };
 
+mod compare;
+mod compress;
 mod error;
+mod executable;
 mod ext;
+mod filter;
 mod index;
 mod package;
+mod reader;
 mod relocate;
+mod shard;
+mod statistics;
+mod stream;
 mod strings;
-
-pub use crate::error::Error;
+mod verify;
+mod warning;
+
+pub use crate::{
+    compress::OutputCompression,
+    error::{Error, InputLocation},
+    executable::ReferencedUnit,
+    filter::UnitFilter,
+    package::{DebugTypeSignature, DwarfObject, DwoId},
+    reader::{DwarfPackageReader, PackageUnit, UnitContribution, UnitDescription},
+    relocate::RelocationMap,
+    shard::{ShardManifest, Sharding},
+    statistics::PackageStatistics,
+    verify::VerificationProblem,
+    warning::Warning,
+};
 
 /// `Session` is expected to be implemented by users of `thorin`, allowing users of `thorin` to
 /// decide how to manage data, rather than `thorin` having arenas internally.
+#[allow(clippy::needless_lifetimes)]
 pub trait Session<Relocations> {
     /// Returns a reference to `data`'s contents with lifetime `'session`.
     fn alloc_data<'session>(&'session self, data: Vec<u8>) -> &'session [u8];
@@ -52,6 +74,10 @@ pub trait Session<Relocations> {
 
     /// Returns a reference to contents of file at `path` with lifetime `'session`.
     fn read_input<'session>(&'session self, path: &Path) -> std::io::Result<&'session [u8]>;
+
+    /// Called with conditions which don't prevent the DWARF package from being created but which
+    /// users may want to know about, such as skipped inputs. Warnings are ignored by default.
+    fn warn(&self, _warning: Warning) {}
 }
 
 /// Should missing DWARF objects referenced by executables be skipped or result in an error?
@@ -77,13 +103,91 @@ impl MissingReferencedObjectBehaviour {
     }
 }
 
+/// Should compilation units with the same `DwoId` as a compilation unit already in the DWARF
+/// package be skipped or result in an error?
+///
+/// Skipped and replaced compilation units are reported with `Warning::DuplicateCompilationUnit`.
+/// Compilation units of a DWARF package being updated which are replaced by a unit from an input
+/// object aren't duplicates (see `DwarfPackage::with_package_to_update`).
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum DuplicateUnitBehaviour {
+    /// Error when encountering duplicate compilation units.
+    Error,
+    /// Keep the first compilation unit with a `DwoId`, skipping later duplicates - useful if the
+    /// same DWARF object is expected to be reachable from multiple inputs.
+    KeepFirst,
+    /// Keep the last compilation unit with a `DwoId`. The contributions of replaced compilation
+    /// units remain in the sections of the DWARF package but aren't in its index.
+    KeepLast,
+    /// Keep the first compilation unit with a `DwoId`, skipping later duplicates if their
+    /// contribution to `.debug_info.dwo` is byte-identical and returning an
+    /// `Error::MismatchedDuplicateUnit` otherwise. Every compilation unit added to the DWARF
+    /// package is kept in memory until the package is finished.
+    KeepIfIdentical,
+}
+
+/// Object file format of the output DWARF package.
+///
+/// Only ELF and Mach-O objects are supported. DWARF packages aren't written in other containers,
+/// such as a raw bundle of sections without an object file around them, as debuggers only read
+/// DWARF packages from object files.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum OutputFormat {
+    /// ELF object, DWARF package sections are named `.debug_info.dwo`, etc.
+    Elf,
+    /// Mach-O object, DWARF package sections are in the `__DWARF` segment and named
+    /// `__debug_info.dwo`, etc. (truncated to sixteen bytes).
+    MachO,
+}
+
+impl OutputFormat {
+    /// Returns the output format matching the format of an input object, if there is one.
+    fn of_input(format: BinaryFormat) -> Option<Self> {
+        match format {
+            BinaryFormat::Elf => Some(OutputFormat::Elf),
+            BinaryFormat::MachO => Some(OutputFormat::MachO),
+            _ => None,
+        }
+    }
+
+    /// Returns the `object::BinaryFormat` corresponding to this output format.
+    pub(crate) fn binary_format(&self) -> BinaryFormat {
+        match *self {
+            OutputFormat::Elf => BinaryFormat::Elf,
+            OutputFormat::MachO => BinaryFormat::MachO,
+        }
+    }
+
+    /// Returns the segment and section name for the section named `elf_name` in ELF objects.
+    pub(crate) fn section_name(&self, elf_name: &str) -> (Vec<u8>, Vec<u8>) {
+        match *self {
+            OutputFormat::Elf => (Vec::new(), Vec::from(elf_name)),
+            OutputFormat::MachO => (Vec::from("__DWARF"), macho_section_name(elf_name).into()),
+        }
+    }
+}
+
 /// Builder for DWARF packages, add input objects/packages with `add_input_object` or input objects
 /// referenced by an executable with `add_executable` before accessing the completed object with
-/// `finish`.
+/// `finish` (or writing it to an output with `finish_to`).
 pub struct DwarfPackage<'output, 'session: 'output, Sess: Session<RelocationMap>> {
     sess: &'session Sess,
-    maybe_in_progress: Option<InProgressDwarfPackage<'output>>,
-    targets: HashSet<DwarfObject>,
+    in_progress: Vec<InProgressDwarfPackage<'output>>,
+    targets: IndexMap<DwarfObject, ReferencedUnit>,
+    output_format: Option<OutputFormat>,
+    streaming_dir: Option<PathBuf>,
+    search_paths: SearchPaths,
+    debug_dirs: Vec<PathBuf>,
+    output_compression: Option<OutputCompression>,
+    compress_index_sections: bool,
+    compare_type_units: bool,
+    package_to_update: Option<PathBuf>,
+    removed_units: HashSet<DwoId>,
+    unit_filter: Option<UnitFilter>,
+    duplicate_unit_behaviour: DuplicateUnitBehaviour,
+    sharding: Option<Sharding>,
+    build_ids: Vec<Vec<u8>>,
+    build_id_note: bool,
 }
 
 impl<'output, 'session: 'output, Sess> fmt::Debug for DwarfPackage<'output, 'session, Sess>
@@ -92,8 +196,22 @@ where
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("DwarfPackage")
-            .field("in_progress", &self.maybe_in_progress)
+            .field("in_progress", &self.in_progress)
             .field("target_count", &self.targets.len())
+            .field("output_format", &self.output_format)
+            .field("streaming_dir", &self.streaming_dir)
+            .field("search_paths", &self.search_paths)
+            .field("debug_dirs", &self.debug_dirs)
+            .field("output_compression", &self.output_compression)
+            .field("compress_index_sections", &self.compress_index_sections)
+            .field("compare_type_units", &self.compare_type_units)
+            .field("package_to_update", &self.package_to_update)
+            .field("removed_units", &self.removed_units)
+            .field("unit_filter", &self.unit_filter)
+            .field("duplicate_unit_behaviour", &self.duplicate_unit_behaviour)
+            .field("sharding", &self.sharding)
+            .field("build_ids", &self.build_ids)
+            .field("build_id_note", &self.build_id_note)
             .finish()
     }
 }
@@ -104,106 +222,415 @@ where
 {
     /// Create a new `DwarfPackage` with the provided `Session` implementation.
     pub fn new(sess: &'session Sess) -> Self {
-        Self { sess, maybe_in_progress: None, targets: HashSet::new() }
+        Self {
+            sess,
+            in_progress: Vec::new(),
+            targets: IndexMap::new(),
+            output_format: None,
+            streaming_dir: None,
+            search_paths: SearchPaths::default(),
+            debug_dirs: Vec::new(),
+            output_compression: None,
+            compress_index_sections: true,
+            compare_type_units: false,
+            package_to_update: None,
+            removed_units: HashSet::new(),
+            unit_filter: None,
+            duplicate_unit_behaviour: DuplicateUnitBehaviour::Error,
+            sharding: None,
+            build_ids: Vec::new(),
+            build_id_note: false,
+        }
+    }
+
+    /// Use `format` as the object file format of the output DWARF package. By default, the format
+    /// of the first input object is used.
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.output_format = Some(format);
+        self
+    }
+
+    /// Write the contents of the DWARF package's sections to temporary files in `dir` as input
+    /// objects are added, rather than accumulating the package in memory. Streamed DWARF packages
+    /// must be elf objects, and should be written to an output with `finish_to` (`finish` reads
+    /// the whole package back into memory).
+    pub fn with_streaming_output(mut self, dir: PathBuf) -> Self {
+        self.streaming_dir = Some(dir);
+        self
+    }
+
+    /// Compress the sections of the DWARF package with `compression`, writing `SHF_COMPRESSED`
+    /// sections. Compressed output is only supported for elf DWARF packages.
+    pub fn with_output_compression(mut self, compression: OutputCompression) -> Self {
+        self.output_compression = Some(compression);
+        self
+    }
+
+    /// Leave the `.debug_cu_index` and `.debug_tu_index` sections of a compressed DWARF package
+    /// uncompressed, so that units can be looked up without decompressing the indexes.
+    pub fn with_uncompressed_index_sections(mut self) -> Self {
+        self.compress_index_sections = false;
+        self
+    }
+
+    /// Compare type units which are skipped because a type unit with the same signature is already
+    /// in the DWARF package with that type unit, calling `Session::warn` with a
+    /// `Warning::MismatchedTypeUnit` if their debugging information entries differ (after reading
+    /// strings from the string tables of each input). Type units with the same signature should be
+    /// identical, so differences indicate a signature collision or a violation of the one
+    /// definition rule.
+    ///
+    /// The debugging information entries of every type unit added to the DWARF package are kept in
+    /// memory until the package is finished.
+    pub fn with_type_unit_comparison(mut self) -> Self {
+        self.compare_type_units = true;
+        self
+    }
+
+    /// Update the existing DWARF package at `path` rather than creating a DWARF package from
+    /// scratch. When the DWARF package is finished, the units of the existing DWARF package are
+    /// added after the units of the input objects, skipping compilation units which were replaced
+    /// by a compilation unit with the same `DwoId` from an input object, or which were removed
+    /// with `remove_input_object`.
+    ///
+    /// Type units of the existing DWARF package are kept (unless replaced by a type unit with the
+    /// same signature from an input object), even if the compilation units which used them were
+    /// removed. If the existing DWARF package has more than one contribution to a section from a
+    /// single input (which isn't the case for DWARF packages created by thorin), then the
+    /// contributions of skipped compilation units to sections other than `.debug_info.dwo` also
+    /// remain in the updated DWARF package.
+    pub fn with_package_to_update(mut self, path: PathBuf) -> Self {
+        self.package_to_update = Some(path);
+        self
+    }
+
+    /// Handle compilation units with the same `DwoId` as a compilation unit which was already added
+    /// to the DWARF package according to `behaviour`. By default, duplicate compilation units are
+    /// an error.
+    pub fn with_duplicate_unit_behaviour(mut self, behaviour: DuplicateUnitBehaviour) -> Self {
+        self.duplicate_unit_behaviour = behaviour;
+        self
+    }
+
+    /// Only add the units of input objects which are selected by `filter` to the DWARF package:
+    /// compilation units selected by their `DwoId` or name, and the type units they reference.
+    /// Other units are skipped, which can be used to create a DWARF package containing a subset
+    /// of the units of an existing DWARF package.
+    pub fn with_unit_filter(mut self, filter: UnitFilter) -> Self {
+        self.unit_filter = Some(filter);
+        self
+    }
+
+    /// Partition the units of the DWARF package into multiple shards according to `sharding`,
+    /// each of which is a complete DWARF package with its own index and string table. Sharded
+    /// DWARF packages must be finished with `finish_shards` or `finish_shards_to`, which also
+    /// return a manifest of the shard containing each compilation unit.
+    pub fn with_sharding(mut self, sharding: Sharding) -> Self {
+        self.sharding = Some(sharding);
+        self
+    }
+
+    /// Write a `.note.gnu.build-id` section to the DWARF package (and to each of its shards),
+    /// containing an `NT_GNU_BUILD_ID` note with the build ID of each executable added with
+    /// `add_executable` or `select_units_referenced_by`, in the order they were added (see
+    /// `build_ids`). No section is written if none of the executables have a build ID. Build ID
+    /// notes are only supported for elf DWARF packages.
+    pub fn with_build_id_note(mut self) -> Self {
+        self.build_id_note = true;
+        self
+    }
+
+    /// Returns the distinct build IDs (from the `NT_GNU_BUILD_ID` notes) of the executables added
+    /// with `add_executable` or `select_units_referenced_by`, in the order they were added.
+    pub fn build_ids(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.build_ids.iter().map(Vec::as_slice)
+    }
+
+    /// Record the build ID of an executable added to the DWARF package, if it has one.
+    fn add_build_id(&mut self, build_id: Option<Vec<u8>>) {
+        match build_id {
+            Some(build_id) if !self.build_ids.contains(&build_id) => {
+                debug!(?build_id, "adding build id");
+                self.build_ids.push(build_id);
+            }
+            _ => (),
+        }
     }
 
-    /// Add an input object to the in-progress package.
-    #[tracing::instrument(level = "trace", skip(obj))]
-    fn process_input_object<'input>(&mut self, obj: &'input object::File<'input>) -> Result<()> {
-        if self.maybe_in_progress.is_none() {
-            self.maybe_in_progress =
-                Some(InProgressDwarfPackage::new(obj.architecture(), obj.endianness()));
+    /// Select the compilation units referenced by the executable at `path` (in addition to any
+    /// units selected by the filter provided with `with_unit_filter`), see `with_unit_filter`.
+    ///
+    /// Errors are `Error::Input`s with the location in the executable where the error occurred.
+    #[tracing::instrument(level = "trace")]
+    pub fn select_units_referenced_by(&mut self, path: &Path) -> Result<()> {
+        self.check_output_configuration()?;
+        let executable =
+            referenced_units(self.sess, path, &self.debug_dirs).map_err(|e| e.in_input(path))?;
+        self.add_build_id(executable.build_id);
+
+        let filter = self.unit_filter.get_or_insert_with(UnitFilter::default);
+        for unit in executable.units {
+            if let DwarfObject::Compilation(dwo_id) = unit.id {
+                filter.add_dwo_id(dwo_id);
+            }
         }
 
-        let encoding = if let Some(section) = obj.section_by_name(".debug_info.dwo") {
-            let data = section.compressed_data()?.decompress()?;
-            let data_ref = self.sess.alloc_owned_cow(data);
-            let debug_info = gimli::DebugInfo::new(data_ref, obj.endianness().as_runtime_endian());
-            debug_info
-                .units()
-                .next()
-                .map_err(Error::ParseUnitHeader)?
-                .map(|root_header| root_header.encoding())
-                .ok_or(Error::NoCompilationUnits)?
+        Ok(())
+    }
+
+    /// Look for DWARF objects referenced by executables in `dir` if they aren't found at the path
+    /// recorded in the executable. Directories are searched in the order they are added, before
+    /// the directory containing the executable.
+    pub fn with_search_dir(mut self, dir: PathBuf) -> Self {
+        self.search_paths.add_dir(dir);
+        self
+    }
+
+    /// Replace the prefix `from` with `to` in the paths to DWARF objects recorded in executables,
+    /// reversing a `-fdebug-prefix-map=to=from` used during compilation. If more than one
+    /// remapping matches a path then the last one added is used.
+    pub fn with_path_remapping(mut self, from: PathBuf, to: PathBuf) -> Self {
+        self.search_paths.add_remapping(from, to);
+        self
+    }
+
+    /// Look for the separate debug files of stripped executables (which contain their skeleton
+    /// units) in `dir`, found by the executable's build ID (at `<dir>/.build-id/xx/yyyy.debug`)
+    /// or `.gnu_debuglink` (at `<dir>/<executable's directory>/<name>`, after the executable's
+    /// directory and its `.debug` subdirectory). Directories are searched in the order they are
+    /// added. If no directories are added, then `/usr/lib/debug` is searched.
+    pub fn with_debug_dir(mut self, dir: PathBuf) -> Self {
+        self.debug_dirs.push(dir);
+        self
+    }
+
+    /// Returns an error if a DWARF package with the output format `format` can't be streamed,
+    /// compressed or have a build ID note, if requested.
+    fn check_output_format(&self, format: OutputFormat) -> Result<()> {
+        if format == OutputFormat::Elf {
+            Ok(())
+        } else if self.streaming_dir.is_some() {
+            Err(Error::UnsupportedStreamingOutputFormat)
+        } else if self.output_compression.is_some() {
+            Err(Error::UnsupportedCompressedOutputFormat)
+        } else if self.build_id_note {
+            Err(Error::UnsupportedBuildIdNoteFormat)
         } else {
-            debug!("no `.debug_info.dwo` in input dwarf object");
-            return Ok(());
-        };
+            Ok(())
+        }
+    }
 
-        let sess = self.sess;
-        self.maybe_in_progress
-            .as_mut()
-            .expect("`process_input_object` is broken")
-            .add_input_object(sess, obj, encoding)
+    /// Returns an error if an output format was provided (see `with_output_format`) which doesn't
+    /// support the requested output options, so that the error is returned before any inputs are
+    /// read rather than once the DWARF package is created or finished.
+    fn check_output_configuration(&self) -> Result<()> {
+        match self.output_format {
+            Some(format) => self.check_output_format(format),
+            None => Ok(()),
+        }
     }
 
-    /// Add input objects referenced by executable to the DWARF package.
-    #[tracing::instrument(level = "trace")]
-    pub fn add_executable(
+    /// Add the prepared input objects from the input at `path` to the in-progress package,
+    /// warning about any archive members that were skipped. `removed_units` is provided if the
+    /// input is the DWARF package being updated.
+    fn add_prepared_inputs(
         &mut self,
         path: &Path,
-        missing_behaviour: MissingReferencedObjectBehaviour,
+        prepared: PreparedInputs<'_>,
+        removed_units: Option<&HashSet<DwoId>>,
     ) -> Result<()> {
-        let data = self.sess.read_input(path).map_err(Error::ReadInput)?;
-        let obj = object::File::parse(data).map_err(Error::ParseObjectFile)?;
-
-        let mut load_section = |id: gimli::SectionId| -> Result<_> {
-            let mut relocations = RelocationMap::default();
-            let data = match obj.section_by_name(&id.name()) {
-                Some(ref section) => {
-                    add_relocations(&mut relocations, &obj, section)?;
-                    section.compressed_data()?.decompress()?
-                }
-                // Use a non-zero capacity so that `ReaderOffsetId`s are unique.
-                None => Cow::Owned(Vec::with_capacity(1)),
-            };
+        let (inputs, skipped_members) = prepared;
+        for member in skipped_members {
+            let location = InputLocation { member: Some(member), ..Default::default() };
+            self.sess.warn(Warning::SkippedArchiveMember(location).in_input(path, None));
+        }
+        for input in inputs {
+            self.add_prepared_input(path, input, removed_units)?;
+        }
 
-            let data_ref = self.sess.alloc_owned_cow(data);
-            let reader = EndianSlice::new(data_ref, obj.endianness().as_runtime_endian());
-            let section = reader;
-            let relocations = self.sess.alloc_relocation(relocations);
-            Ok(Relocate { relocations, section, reader })
+        Ok(())
+    }
+
+    /// Add a prepared input object from the input at `path` to the in-progress package (or to the
+    /// in-progress packages of the shards its units are added to).
+    #[tracing::instrument(level = "trace", skip(input))]
+    fn add_prepared_input(
+        &mut self,
+        path: &Path,
+        input: PreparedInput<'_>,
+        removed_units: Option<&HashSet<DwoId>>,
+    ) -> Result<()> {
+        let sess = self.sess;
+        let member = input.member.as_deref();
+        let mut warn = |warning: Warning| sess.warn(warning.in_input(path, member));
+        let result = match &input.contents {
+            Some(contents) => {
+                let location = InputLocation {
+                    path: path.to_path_buf(),
+                    member: input.member.clone(),
+                    ..Default::default()
+                };
+                self.add_prepared_contents(&input, contents, &location, removed_units, &mut warn)
+            }
+            None => self.shard(self.in_progress.len().saturating_sub(1), &input).map(|package| {
+                package.count_input_object();
+                warn(Warning::NoDwarfObject(InputLocation::default()));
+            }),
         };
+        match member {
+            Some(member) => result.map_err(|e| e.in_member(member)),
+            None => result,
+        }
+    }
 
-        let dwarf = gimli::Dwarf::load(&mut load_section)?;
+    /// Add the DWARF contents of the prepared input object `input` from `location` to the
+    /// in-progress package, or to the in-progress packages of the shards its units are added to.
+    fn add_prepared_contents(
+        &mut self,
+        input: &PreparedInput<'_>,
+        contents: &PreparedContents<'_>,
+        location: &InputLocation,
+        removed_units: Option<&HashSet<DwoId>>,
+        warn: &mut dyn FnMut(Warning),
+    ) -> Result<()> {
+        let skipped_units =
+            skipped_units(contents, &self.in_progress, removed_units, self.unit_filter.as_ref())?;
+        let shards = match self.sharding {
+            Some(sharding) => self.assign_shards(contents, &skipped_units, sharding)?,
+            None => vec![(0, skipped_units)],
+        };
 
-        let mut iter = dwarf.units();
-        while let Some(header) = iter.next().map_err(Error::ParseUnitHeader)? {
-            let unit = dwarf.unit(header).map_err(Error::ParseUnit)?;
+        let duplicate_behaviour = self.duplicate_unit_behaviour;
+        for (shard, skipped_units) in shards {
+            let package = self.shard(shard, input)?;
+            package.count_input_object();
+            package.add_input_object(
+                contents,
+                location,
+                &skipped_units,
+                duplicate_behaviour,
+                warn,
+            )?;
+        }
 
-            let target = match dwo_identifier_of_unit(&dwarf.debug_abbrev, &unit.header)? {
-                Some(target) => target,
-                None => {
-                    debug!("no target");
-                    continue;
+        Ok(())
+    }
+
+    /// Returns the shards that the units of `contents` which aren't in `skipped_units` are added
+    /// to, and the units of `contents` which are skipped when adding it to each shard.
+    fn assign_shards(
+        &self,
+        contents: &PreparedContents<'_>,
+        skipped_units: &HashSet<DwarfObject>,
+        sharding: Sharding,
+    ) -> Result<Vec<(usize, HashSet<DwarfObject>)>> {
+        // Units are added to the last shard when sharding by size, until it is full.
+        let mut current = self.in_progress.len().saturating_sub(1);
+        let (mut current_size, mut current_is_empty) = match self.in_progress.get(current) {
+            Some(package) => (package.size(), package.contained_units().is_empty()),
+            None => (0, true),
+        };
+
+        let mut shards: IndexMap<usize, HashSet<DwarfObject>> = IndexMap::new();
+        for group in contents.unit_groups(skipped_units)? {
+            if group.units.is_empty() {
+                continue;
+            }
+
+            let containing_shard = group.compilation_unit.and_then(|dwo_id| {
+                let id = DwarfObject::Compilation(dwo_id);
+                self.in_progress.iter().position(|package| package.contained_units().contains(&id))
+            });
+            let shard = match (containing_shard, sharding) {
+                // Duplicate compilation units are added to the shard with the unit they duplicate,
+                // so that they are handled according to the `DuplicateUnitBehaviour`.
+                (Some(shard), _) => shard,
+                (None, Sharding::ByDwoId(count)) => group
+                    .compilation_unit
+                    .map_or(0, |dwo_id| (dwo_id.0 % count.get() as u64) as usize),
+                (None, Sharding::BySize(budget)) => {
+                    if !current_is_empty && current_size + group.size > budget {
+                        current += 1;
+                        current_size = 0;
+                    }
+                    current_is_empty = false;
+                    current_size += group.size;
+                    current
                 }
             };
+            shards.entry(shard).or_default().extend(group.units);
+        }
 
-            let dwo_name = {
-                let mut cursor = unit.header.entries(&unit.abbreviations);
-                cursor.next_dfs()?;
-                let root = cursor.current().expect("unit w/out root debugging information entry");
-
-                let dwo_name = if let Some(val) = root.attr_value(gimli::DW_AT_dwo_name)? {
-                    // DWARF 5
-                    val
-                } else if let Some(val) = root.attr_value(gimli::DW_AT_GNU_dwo_name)? {
-                    // GNU Extension
-                    val
-                } else {
-                    return Err(Error::MissingDwoName(target.index()));
-                };
+        // Inputs without any units which aren't skipped are still counted as an input of a shard.
+        if shards.is_empty() {
+            return Ok(vec![(current, skipped_units.clone())]);
+        }
 
-                dwarf.attr_string(&unit, dwo_name)?.to_string()?.into_owned()
-            };
+        Ok(shards
+            .into_iter()
+            .map(|(shard, units)| {
+                (shard, contents.units().filter(|id| !units.contains(id)).collect())
+            })
+            .collect())
+    }
 
-            // Prepend the compilation directory if it exists.
-            let mut path = if let Some(comp_dir) = &unit.comp_dir {
-                PathBuf::from(comp_dir.to_string()?.into_owned())
-            } else {
-                PathBuf::new()
-            };
-            path.push(dwo_name);
+    /// Returns the in-progress package of the shard `shard` (or the in-progress package if the
+    /// package isn't sharded), creating it and any earlier shards using the format of `input`
+    /// (unless an output format was provided) if necessary.
+    fn shard(
+        &mut self,
+        shard: usize,
+        input: &PreparedInput<'_>,
+    ) -> Result<&mut InProgressDwarfPackage<'output>> {
+        // Every shard is created at once when sharding by `DwoId`, so that empty shards are kept.
+        let count = match self.sharding {
+            Some(Sharding::ByDwoId(count)) => count.get().max(shard + 1),
+            _ => shard + 1,
+        };
+        while self.in_progress.len() < count {
+            let format = self
+                .output_format
+                .or_else(|| OutputFormat::of_input(input.format))
+                .unwrap_or(OutputFormat::Elf);
+            self.check_output_format(format)?;
+            self.in_progress.push(InProgressDwarfPackage::new(
+                format,
+                input.architecture,
+                input.endianness,
+                self.streaming_dir.as_deref(),
+                self.output_compression,
+                self.compress_index_sections,
+                self.compare_type_units,
+            )?);
+        }
+
+        Ok(&mut self.in_progress[shard])
+    }
+
+    /// Returns `true` if the unit `id` is in the in-progress package (or any of its shards).
+    fn contains_unit(&self, id: DwarfObject) -> bool {
+        self.in_progress.iter().any(|package| package.contained_units().contains(&id))
+    }
+
+    /// Add input objects referenced by executable to the DWARF package, recording the build ID of
+    /// the executable (see `build_ids`). `path` can also be an archive (e.g. a static library), in
+    /// which case the input objects referenced by each object in the archive are added.
+    ///
+    /// Errors are `Error::Input`s with the location in the executable or referenced input object
+    /// where the error occurred.
+    #[tracing::instrument(level = "trace")]
+    pub fn add_executable(
+        &mut self,
+        path: &Path,
+        missing_behaviour: MissingReferencedObjectBehaviour,
+    ) -> Result<()> {
+        self.check_output_configuration()?;
+        let executable =
+            referenced_units(self.sess, path, &self.debug_dirs).map_err(|e| e.in_input(path))?;
+        self.add_build_id(executable.build_id);
+
+        for unit in executable.units {
+            let target = unit.id;
 
             // Only add `DwoId`s to the targets, not `DebugTypeSignature`s. There doesn't
             // appear to be a "skeleton type unit" to find the corresponding unit of (there are
@@ -213,20 +640,37 @@ where
                 // Input objects are processed first, if a DWARF object referenced by this
                 // executable was already found then don't add it to the target and try to add it
                 // again.
-                if let Some(package) = &self.maybe_in_progress {
-                    if package.contained_units().contains(&target) {
-                        continue;
-                    }
+                if self.contains_unit(target) {
+                    continue;
                 }
 
                 debug!(?target, "adding target");
-                self.targets.insert(target);
+                self.targets.entry(target).or_insert_with(|| unit.clone());
             }
 
-            match self.add_input_object(&path) {
-                Ok(()) => (),
-                Err(Error::ReadInput(..)) if missing_behaviour.skip_missing() => (),
-                Err(e) => return Err(e),
+            // Try each of the paths that the DWARF object could be at, only reporting a failure
+            // to read the first if none of them could be read.
+            let mut first_read_error = None;
+            for candidate in self.search_paths.candidates(&unit) {
+                match self.add_input_object(&candidate) {
+                    Ok(()) => {
+                        first_read_error = None;
+                        break;
+                    }
+                    Err(e) if matches!(e.without_location(), Error::ReadInput(..)) => {
+                        trace!(?candidate, "not found");
+                        first_read_error.get_or_insert(e);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            match first_read_error {
+                Some(_) if missing_behaviour.skip_missing() => {
+                    self.sess.warn(Warning::MissingReferencedObject(unit))
+                }
+                Some(e) => return Err(e),
+                None => (),
             }
         }
 
@@ -235,76 +679,304 @@ where
 
     /// Add an input object to the DWARF package.
     ///
-    /// Input object must be an archive or an elf object.
+    /// Input object must be an archive, an elf object or a mach-o object. Errors are
+    /// `Error::Input`s with the location in the input where the error occurred.
     #[tracing::instrument(level = "trace")]
     pub fn add_input_object(&mut self, path: &Path) -> Result<()> {
-        let data = self.sess.read_input(&path).map_err(Error::ReadInput)?;
-
-        let kind = FileKind::parse(data).map_err(Error::ParseFileKind)?;
-        trace!(?kind);
-        match kind {
-            FileKind::Archive => {
-                let archive = object::read::archive::ArchiveFile::parse(data)
-                    .map_err(Error::ParseArchiveFile)?;
-
-                for member in archive.members() {
-                    let member = member.map_err(Error::ParseArchiveMember)?;
-                    let data = member.data(data)?;
-
-                    let kind = if let Ok(kind) = FileKind::parse(data) {
-                        kind
-                    } else {
-                        trace!("skipping non-elf archive member");
-                        continue;
-                    };
-
-                    trace!(?kind, "archive member");
-                    match kind {
-                        FileKind::Elf32 | FileKind::Elf64 => {
-                            let obj = object::File::parse(data).map_err(Error::ParseObjectFile)?;
-                            self.process_input_object(&obj)?;
-                        }
-                        _ => {
-                            trace!("skipping non-elf archive member");
-                        }
-                    }
-                }
+        self.add_input(path, None)
+    }
 
-                Ok(())
-            }
-            FileKind::Elf32 | FileKind::Elf64 => {
-                let obj = object::File::parse(data).map_err(Error::ParseObjectFile)?;
-                self.process_input_object(&obj)
+    /// Remove the compilation units of the DWARF objects in the input at `path` from the DWARF
+    /// package being updated, see `with_package_to_update`.
+    ///
+    /// Input object must be an archive, an elf object or a mach-o object. Errors are
+    /// `Error::Input`s with the location in the input where the error occurred.
+    #[tracing::instrument(level = "trace")]
+    pub fn remove_input_object(&mut self, path: &Path) -> Result<()> {
+        let data = self.sess.read_input(path).map_err(|e| Error::ReadInput(e).in_input(path))?;
+        let (inputs, _) = prepare_input_objects(data).map_err(|e| e.in_input(path))?;
+        for contents in inputs.iter().filter_map(|input| input.contents.as_ref()) {
+            self.removed_units.extend(contents.compilation_units());
+        }
+
+        Ok(())
+    }
+
+    /// Read the input at `path` and add its input objects to the DWARF package. `removed_units`
+    /// is provided if the input is the DWARF package being updated.
+    fn add_input(&mut self, path: &Path, removed_units: Option<&HashSet<DwoId>>) -> Result<()> {
+        self.check_output_configuration()?;
+        let data = self.sess.read_input(path).map_err(|e| Error::ReadInput(e).in_input(path))?;
+        let prepared = prepare_input_objects(data).map_err(|e| e.in_input(path))?;
+        self.add_prepared_inputs(path, prepared, removed_units).map_err(|e| e.in_input(path))
+    }
+
+    /// Add multiple input objects to the DWARF package, preparing the input objects in parallel.
+    ///
+    /// Inputs are read and added to the package in order, so the resulting DWARF package is
+    /// identical to the one produced by calling `add_input_object` with each input. Input objects
+    /// must be archives, elf objects or mach-o objects.
+    ///
+    /// Errors are `Error::Input`s with the location in the first input that couldn't be added
+    /// where the error occurred.
+    #[cfg(feature = "rayon")]
+    #[tracing::instrument(level = "trace", skip(paths))]
+    pub fn add_input_objects<P>(&mut self, paths: &[P]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        use rayon::prelude::*;
+
+        /// Number of inputs which are prepared before being added to the package, limits the
+        /// number of decompressed inputs kept in memory at once.
+        const BATCH_SIZE: usize = 256;
+
+        self.check_output_configuration()?;
+        for batch in paths.chunks(BATCH_SIZE) {
+            let datas: Vec<_> = batch
+                .iter()
+                .map(|path| self.sess.read_input(path.as_ref()).map_err(Error::ReadInput))
+                .collect();
+            let prepared: Vec<_> =
+                datas.into_par_iter().map(|data| data.and_then(prepare_input_objects)).collect();
+
+            // Errors are reported for the first failing input, as if inputs were added serially.
+            for (path, inputs) in batch.iter().zip(prepared) {
+                inputs
+                    .and_then(|inputs| self.add_prepared_inputs(path.as_ref(), inputs, None))
+                    .map_err(|e| e.in_input(path.as_ref()))?;
             }
-            _ => Err(Error::InvalidInputKind),
         }
+
+        Ok(())
     }
 
-    /// Returns the `object::write::Object` containing the created DWARF package.
+    /// Returns the `OutputObject` containing each created DWARF package (or shard of a sharded
+    /// DWARF package) and statistics about it, and the shard manifest of the DWARF package.
+    fn finish_outputs(
+        mut self,
+    ) -> Result<(Vec<(OutputObject<'output>, PackageStatistics)>, ShardManifest)> {
+        self.check_output_configuration()?;
+        if let Some(path) = self.package_to_update.take() {
+            let removed_units = std::mem::take(&mut self.removed_units);
+            self.add_input(&path, Some(&removed_units))?;
+        }
+
+        let missing: Vec<_> = std::mem::take(&mut self.targets)
+            .into_iter()
+            .filter(|(target, _)| !self.contains_unit(*target))
+            .map(|(_, unit)| unit)
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::MissingReferencedUnits(missing));
+        }
+
+        if self.in_progress.is_empty() {
+            return Err(Error::NoOutputObjectCreated);
+        }
+
+        let compilation_units = self
+            .in_progress
+            .iter()
+            .enumerate()
+            .flat_map(|(shard, package)| {
+                package.contained_units().iter().filter_map(move |id| match id {
+                    DwarfObject::Compilation(dwo_id) => Some((*dwo_id, shard)),
+                    DwarfObject::Type(_) => None,
+                })
+            })
+            .collect();
+        let manifest = ShardManifest::new(self.in_progress.len(), compilation_units);
+        let build_ids = if self.build_id_note { &self.build_ids[..] } else { &[] };
+        let outputs = self
+            .in_progress
+            .into_iter()
+            .map(|package| package.finish(build_ids))
+            .collect::<Result<_>>()?;
+        Ok((outputs, manifest))
+    }
+
+    /// Returns the `OutputObject` containing the created DWARF package and statistics about it.
+    fn finish_output(self) -> Result<(OutputObject<'output>, PackageStatistics)> {
+        if self.sharding.is_some() {
+            return Err(Error::ShardedOutputRequiresShards);
+        }
+
+        let (mut outputs, _) = self.finish_outputs()?;
+        Ok(outputs.remove(0))
+    }
+
+    /// Returns the `object::write::Object` containing the created DWARF package. If the DWARF
+    /// package is being streamed to temporary files, then the contents of its sections are read
+    /// into memory, use `finish_to` to avoid this.
     ///
-    /// Returns an `Error::MissingReferencedUnit` if DWARF objects referenced by executables were
+    /// Returns an `Error::MissingReferencedUnits` if DWARF objects referenced by executables were
     /// not subsequently found.
     /// Returns an `Error::NoOutputObjectCreated` if no input objects or executables were provided.
+    /// Returns an `Error::Input` if the DWARF package being updated couldn't be added (see
+    /// `with_package_to_update`).
+    /// Returns an `Error::ShardedOutputRequiresShards` if the DWARF package is sharded (see
+    /// `with_sharding`), use `finish_shards` instead.
+    /// Returns an `Error::UnsupportedBuildIdNoteFormat` if a build ID note was requested for a
+    /// mach-o DWARF package (see `with_build_id_note`).
     #[tracing::instrument(level = "trace")]
     pub fn finish(self) -> Result<WritableObject<'output>> {
-        match self.maybe_in_progress {
-            Some(package) => {
-                if let Some(missing) = self.targets.difference(package.contained_units()).next() {
-                    return Err(Error::MissingReferencedUnit(missing.index()));
-                }
+        self.finish_with_statistics().map(|(obj, _)| obj)
+    }
 
-                package.finish()
-            }
-            None if !self.targets.is_empty() => {
-                let first_missing_unit = self
-                    .targets
-                    .iter()
-                    .next()
-                    .copied()
-                    .expect("non-empty map doesn't have first element");
-                Err(Error::MissingReferencedUnit(first_missing_unit.index()))
+    /// Returns the `object::write::Object` containing the created DWARF package, and statistics
+    /// about the inputs and the DWARF package (such as the number of type units that were
+    /// deduplicated, and the size of each section).
+    ///
+    /// Returns the same errors as `finish`.
+    #[tracing::instrument(level = "trace")]
+    pub fn finish_with_statistics(self) -> Result<(WritableObject<'output>, PackageStatistics)> {
+        let (obj, statistics) = self.finish_output()?;
+        Ok((obj.into_object()?, statistics))
+    }
+
+    /// Write the created DWARF package to `output`.
+    ///
+    /// If the DWARF package is being streamed to temporary files, then the contents of sections
+    /// are copied from the temporary files to `output` without reading the whole package into
+    /// memory.
+    ///
+    /// Returns the same errors as `finish`.
+    #[tracing::instrument(level = "trace", skip(output))]
+    pub fn finish_to<W: io::Write>(self, output: W) -> Result<()> {
+        self.finish_to_with_statistics(output).map(|_| ())
+    }
+
+    /// Write the created DWARF package to `output`, returning statistics about the inputs and the
+    /// DWARF package (see `finish_with_statistics`).
+    ///
+    /// Returns the same errors as `finish_to`.
+    #[tracing::instrument(level = "trace", skip(output))]
+    pub fn finish_to_with_statistics<W: io::Write>(self, output: W) -> Result<PackageStatistics> {
+        let (obj, statistics) = self.finish_output()?;
+        obj.write(output)?;
+        Ok(statistics)
+    }
+
+    /// Returns the `object::write::Object` containing each shard of the created DWARF package (or
+    /// only the DWARF package, if it isn't sharded), and a manifest of the shard containing each
+    /// compilation unit. As with `finish`, shards which are being streamed to temporary files are
+    /// read into memory, use `finish_shards_to` to avoid this.
+    ///
+    /// Returns the same errors as `finish`, except for `Error::ShardedOutputRequiresShards`.
+    #[tracing::instrument(level = "trace")]
+    pub fn finish_shards(self) -> Result<(Vec<WritableObject<'output>>, ShardManifest)> {
+        let (outputs, manifest) = self.finish_outputs()?;
+        let objs = outputs.into_iter().map(|(obj, _)| obj.into_object()).collect::<Result<_>>()?;
+        Ok((objs, manifest))
+    }
+
+    /// Write each shard of the created DWARF package (or only the DWARF package, if it isn't
+    /// sharded) to the writer returned by `output` for the index of the shard (which is flushed
+    /// after the shard is written), returning a manifest of the shard containing each compilation
+    /// unit.
+    ///
+    /// Returns the same errors as `finish_to`, except for `Error::ShardedOutputRequiresShards`.
+    #[tracing::instrument(level = "trace", skip(output))]
+    pub fn finish_shards_to<W, F>(self, mut output: F) -> Result<ShardManifest>
+    where
+        W: io::Write,
+        F: FnMut(usize) -> io::Result<W>,
+    {
+        let (outputs, manifest) = self.finish_outputs()?;
+        for (shard, (obj, _)) in outputs.into_iter().enumerate() {
+            let mut writer = output(shard).map_err(Error::WriteOutput)?;
+            obj.write(&mut writer)?;
+            writer.flush().map_err(Error::WriteOutput)?;
+        }
+        Ok(manifest)
+    }
+}
+
+/// Returns the units of the prepared input object `contents` which shouldn't be added to the
+/// `in_progress` package (or its shards): compilation units of the DWARF package being updated
+/// which were replaced or are in `removed_units`, and units which aren't selected by
+/// `unit_filter`.
+fn skipped_units(
+    contents: &PreparedContents<'_>,
+    in_progress: &[InProgressDwarfPackage<'_>],
+    removed_units: Option<&HashSet<DwoId>>,
+    unit_filter: Option<&UnitFilter>,
+) -> Result<HashSet<DwarfObject>> {
+    let mut skipped_units = HashSet::new();
+    if let Some(removed_units) = removed_units {
+        let replaced = |dwo_id: &DwoId| {
+            let id = DwarfObject::Compilation(*dwo_id);
+            in_progress.iter().any(|package| package.contained_units().contains(&id))
+        };
+        skipped_units.extend(
+            contents
+                .compilation_units()
+                .filter(|dwo_id| removed_units.contains(dwo_id) || replaced(dwo_id))
+                .map(DwarfObject::Compilation),
+        );
+    }
+    if let Some(unit_filter) = unit_filter {
+        let selected_units = contents.selected_units(unit_filter)?;
+        skipped_units.extend(contents.units().filter(|id| !selected_units.contains(id)));
+    }
+
+    Ok(skipped_units)
+}
+
+/// Prepared input objects from an input, and the names of any archive members which were skipped
+/// because they aren't objects.
+type PreparedInputs<'input> = (Vec<PreparedInput<'input>>, Vec<String>);
+
+/// Parse and prepare the input objects in `data`, which must be an archive, an elf object or a
+/// mach-o object.
+#[tracing::instrument(level = "trace", skip(data))]
+fn prepare_input_objects(data: &[u8]) -> Result<PreparedInputs<'_>> {
+    let kind = FileKind::parse(data).map_err(Error::ParseFileKind)?;
+    trace!(?kind);
+    match kind {
+        FileKind::Archive => {
+            let archive =
+                object::read::archive::ArchiveFile::parse(data).map_err(Error::ParseArchiveFile)?;
+
+            let mut inputs = Vec::new();
+            let mut skipped_members = Vec::new();
+            for member in archive.members() {
+                let member = member.map_err(Error::ParseArchiveMember)?;
+                let name = String::from_utf8_lossy(member.name());
+                let data = member.data(data).map_err(|e| Error::from(e).in_member(&name))?;
+
+                let kind = if let Ok(kind) = FileKind::parse(data) {
+                    kind
+                } else {
+                    trace!("skipping non-object archive member");
+                    skipped_members.push(name.into_owned());
+                    continue;
+                };
+
+                trace!(?kind, "archive member");
+                match kind {
+                    FileKind::Elf32 | FileKind::Elf64 | FileKind::MachO32 | FileKind::MachO64 => {
+                        let mut input = object::File::parse(data)
+                            .map_err(Error::ParseObjectFile)
+                            .and_then(|obj| PreparedInput::new(&obj))
+                            .map_err(|e| e.in_member(&name))?;
+                        input.member = Some(name.into_owned());
+                        inputs.push(input);
+                    }
+                    _ => {
+                        trace!("skipping non-object archive member");
+                        skipped_members.push(name.into_owned());
+                    }
+                }
             }
-            None => Err(Error::NoOutputObjectCreated),
+
+            Ok((inputs, skipped_members))
+        }
+        FileKind::Elf32 | FileKind::Elf64 | FileKind::MachO32 | FileKind::MachO64 => {
+            let obj = object::File::parse(data).map_err(Error::ParseObjectFile)?;
+            Ok((vec![PreparedInput::new(&obj)?], Vec::new()))
         }
+        _ => Err(Error::InvalidInputKind),
     }
 }
@@ -1,25 +1,43 @@
-use std::{collections::HashSet, fmt};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt, io,
+    ops::Range,
+    path::Path,
+};
 
-use gimli::{Encoding, RunTimeEndian, UnitHeader, UnitIndex, UnitSectionOffset, UnitType};
+use gimli::{
+    write::{EndianVec, Writer},
+    Encoding, RunTimeEndian, UnitHeader, UnitIndex, UnitSectionOffset, UnitType,
+};
+use indexmap::IndexMap;
 use object::{
-    write::{Object as WritableObject, SectionId},
-    BinaryFormat, Object, ObjectSection, SectionKind,
+    elf,
+    write::{Object as WritableObject, SectionId, StreamingBuffer},
+    BinaryFormat, Object, ObjectSection, SectionFlags, SectionKind,
 };
 use tracing::debug;
 
 use crate::{
-    error::{Error, Result},
-    ext::{CompressedDataRangeExt, EndianityExt, IndexSectionExt, PackageFormatExt},
+    compare::TypeUnitTree,
+    compress::{
+        compress_section, compressed_section_align, uncompressed_section_align, OutputCompression,
+    },
+    error::{Error, InputLocation, Result},
+    ext::{DwoSectionIdExt, EndianityExt, IndexSectionExt, PackageFormatExt},
+    filter::{referenced_type_units, unit_name, UnitFilter},
     index::{write_index, Bucketable, Contribution, ContributionOffset, IndexEntry},
-    relocate::RelocationMap,
-    strings::PackageStringTable,
-    Session,
+    statistics::PackageStatistics,
+    stream::{StreamingObject, StreamingSectionId},
+    strings::{read_str_offsets_section, str_offsets_contribution_strings, PackageStringTable},
+    warning::Warning,
+    DuplicateUnitBehaviour, OutputFormat,
 };
 
 /// New-type'd index (constructed from `gimli::DwoId`) with a custom `Debug` implementation to
 /// print in hexadecimal.
 #[derive(Copy, Clone, Eq, Hash, PartialEq)]
-pub(crate) struct DwoId(pub(crate) u64);
+pub struct DwoId(pub u64);
 
 impl Bucketable for DwoId {
     fn index(&self) -> u64 {
@@ -42,7 +60,7 @@ impl From<gimli::DwoId> for DwoId {
 /// New-type'd index (constructed from `gimli::DebugTypeSignature`) with a custom `Debug`
 /// implementation to print in hexadecimal.
 #[derive(Copy, Clone, Eq, Hash, PartialEq)]
-pub(crate) struct DebugTypeSignature(pub(crate) u64);
+pub struct DebugTypeSignature(pub u64);
 
 impl Bucketable for DebugTypeSignature {
     fn index(&self) -> u64 {
@@ -64,7 +82,7 @@ impl From<gimli::DebugTypeSignature> for DebugTypeSignature {
 
 /// Identifier for a DWARF object.
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
-pub(crate) enum DwarfObject {
+pub enum DwarfObject {
     /// `DwoId` identifying compilation units.
     Compilation(DwoId),
     /// `DebugTypeSignature` identifying type units.
@@ -105,7 +123,7 @@ pub(crate) fn dwo_identifier_of_unit<R: gimli::Reader>(
         // Compilation units with GNU Extension
         UnitType::Compilation => {
             let abbreviations =
-                header.abbreviations(&debug_abbrev).map_err(Error::ParseUnitAbbreviations)?;
+                header.abbreviations(debug_abbrev).map_err(Error::ParseUnitAbbreviations)?;
             let mut cursor = header.entries(&abbreviations);
             cursor.next_dfs()?;
             let root = cursor.current().ok_or(Error::NoDie)?;
@@ -115,11 +133,10 @@ pub(crate) fn dwo_identifier_of_unit<R: gimli::Reader>(
             }
             let mut attrs = root.attrs();
             while let Some(attr) = attrs.next().map_err(Error::ParseUnitAttribute)? {
-                match (attr.name(), attr.value()) {
-                    (gimli::constants::DW_AT_GNU_dwo_id, gimli::AttributeValue::DwoId(dwo_id)) => {
-                        return Ok(Some(DwarfObject::Compilation(dwo_id.into())))
-                    }
-                    _ => (),
+                if let (gimli::constants::DW_AT_GNU_dwo_id, gimli::AttributeValue::DwoId(dwo_id)) =
+                    (attr.name(), attr.value())
+                {
+                    return Ok(Some(DwarfObject::Compilation(dwo_id.into())));
                 }
             }
 
@@ -136,6 +153,58 @@ pub(crate) fn dwo_identifier_of_unit<R: gimli::Reader>(
     }
 }
 
+/// Returns the offset of the `.debug_abbrev.dwo` contribution of each compilation unit in an input
+/// DWARF package with the index `cu_index` (if the input is a DWARF package), by the offset of the
+/// unit's contribution to `.debug_info.dwo`.
+fn abbrev_contribution_offsets<R: gimli::Reader>(
+    cu_index: Option<&UnitIndex<R>>,
+) -> Result<HashMap<usize, usize>> {
+    let mut offsets = HashMap::new();
+    let cu_index = match cu_index {
+        Some(cu_index) => cu_index,
+        None => return Ok(offsets),
+    };
+
+    for row_id in 1..=cu_index.unit_count() {
+        let (mut info_offset, mut abbrev_offset) = (None, None);
+        for section in cu_index.sections(row_id).map_err(|e| Error::RowNotInIndex(e, row_id))? {
+            match section.section {
+                gimli::SectionId::DebugInfo => info_offset = Some(section.offset as usize),
+                gimli::SectionId::DebugAbbrev => abbrev_offset = Some(section.offset as usize),
+                _ => (),
+            }
+        }
+        if let (Some(info_offset), Some(abbrev_offset)) = (info_offset, abbrev_offset) {
+            offsets.insert(info_offset, abbrev_offset);
+        }
+    }
+
+    Ok(offsets)
+}
+
+/// Returns the decompressed data of `section`, reporting errors as occurring in the section.
+fn decompress_section<'input>(section: &object::Section<'input, '_>) -> Result<Cow<'input, [u8]>> {
+    let data = section.compressed_data().and_then(|data| data.decompress());
+    data.map_err(|e| match section.name() {
+        Ok(name) => Error::from(e).in_section(name, None),
+        Err(_) => Error::from(e),
+    })
+}
+
+/// Returns the first section of `input` which `DwoSectionIdExt::from_dwo_section` identifies as
+/// `id`, if there is one.
+fn dwo_section_by_id<'input, 'file>(
+    input: &'file object::File<'input>,
+    id: gimli::SectionId,
+) -> Result<Option<object::Section<'input, 'file>>> {
+    for section in input.sections() {
+        if gimli::SectionId::from_dwo_section(input, &section)? == Some(id) {
+            return Ok(Some(section));
+        }
+    }
+    Ok(None)
+}
+
 /// Wrapper around `.debug_info.dwo` and `debug_types.dwo` unit iterators for uniform handling.
 enum UnitHeaderIterator<R: gimli::Reader> {
     DebugInfo(gimli::read::DebugInfoUnitHeadersIter<R>),
@@ -151,36 +220,31 @@ impl<R: gimli::Reader> UnitHeaderIterator<R> {
     }
 }
 
-/// Returns the parsed unit index from a `.debug_{cu,tu}_index` section.
-pub(crate) fn maybe_load_index_section<'input, 'session: 'input, Endian, Index, R, Sess>(
-    sess: &'session Sess,
+/// Returns the parsed unit index from the data of a `.debug_{cu,tu}_index` section (if it
+/// exists).
+pub(crate) fn maybe_load_index_section<'input, Endian, Index, R>(
     encoding: Encoding,
     endian: Endian,
-    input: &object::File<'input>,
+    index_data: Option<&'input [u8]>,
 ) -> Result<Option<UnitIndex<R>>>
 where
     Endian: gimli::Endianity,
     Index: IndexSectionExt<'input, Endian, R>,
     R: gimli::Reader,
-    Sess: Session<RelocationMap>,
 {
     let index_name = Index::id().dwo_name().expect("index id w/out known value");
-    if let Some(index_section) = input.section_by_name(index_name) {
-        let index_data = index_section
-            .compressed_data()
-            .and_then(|d| d.decompress())
-            .map_err(Error::DecompressData)?;
-        let index_data_ref = sess.alloc_owned_cow(index_data);
-        let unit_index = Index::new(index_data_ref, endian)
-            .index()
-            .map_err(|e| Error::ParseIndex(e, index_name.to_string()))?;
+    if let Some(index_data) = index_data {
+        let unit_index = Index::new(index_data, endian).index().map_err(|e| {
+            Error::ParseIndex(e, index_name.to_string()).in_section(index_name, None)
+        })?;
 
         if !encoding.is_compatible_dwarf_package_index_version(unit_index.version()) {
             return Err(Error::IncompatibleIndexVersion(
                 index_name.to_string(),
                 encoding.dwarf_package_index_version(),
                 unit_index.version(),
-            ));
+            )
+            .in_section(index_name, None));
         }
 
         Ok(Some(unit_index))
@@ -189,6 +253,33 @@ where
     }
 }
 
+/// Returns the offset of the contribution of the unit `id` to `section` in an input DWARF package
+/// with the index `index`, or zero if the input isn't a DWARF package.
+fn input_contribution_offset<R: gimli::Reader>(
+    index: Option<&UnitIndex<R>>,
+    id: DwarfObject,
+    section: gimli::SectionId,
+) -> Result<usize> {
+    let index = match index {
+        Some(index) => index,
+        None => return Ok(0),
+    };
+
+    let idx = id.index();
+    let row_id = index.find(idx).ok_or(Error::UnitNotInIndex(idx))?;
+    let offset = index
+        .sections(row_id)
+        .map_err(|e| Error::RowNotInIndex(e, row_id))?
+        .find(|index_section| index_section.section == section)
+        .map_or(0, |index_section| index_section.offset);
+    Ok(offset as usize)
+}
+
+/// Closure which adjusts the contribution of a unit from an input, see
+/// `create_contribution_adjustor`.
+pub(crate) type ContributionAdjustor<'input> =
+    Box<dyn FnMut(DwarfObject, Option<Contribution>) -> Result<Option<Contribution>> + 'input>;
+
 /// Returns a closure which takes an identifier and a `Option<Contribution>`, and returns an
 /// adjusted contribution if the input file is a DWARF package (and the contribution was
 /// present).
@@ -205,44 +296,46 @@ where
 /// Given a parsed index section, use the size of its contribution to `.debug_str_offsets` as the
 /// size of its contribution in the new unit (without this, it would be the size of the entire
 /// `.debug_str_offsets` section from the input, rather than the part that the compilation unit
-/// originally contributed to that). For subsequent units from the input, the offset in the
-/// contribution will need to be adjusted to based on the size of the previous units.
+/// originally contributed to that). Sections of an input DWARF package are copied into the new
+/// DWARF package in their entirety, so the offset of the unit's contribution in the input's
+/// section is added to the offset of the input's section in the new DWARF package.
 ///
 /// This function returns a "contribution adjustor" closure, which adjusts the contribution's
-/// offset and size according to its contribution in the input's index and with an offset
-/// accumulated over all calls to the closure.
-pub(crate) fn create_contribution_adjustor<'input, R: 'input>(
+/// offset and size according to its contribution in the input's index. Units can share
+/// contributions and can be skipped (e.g. duplicate type units), so the adjusted contribution
+/// only depends on the unit's row in the index.
+pub(crate) fn create_contribution_adjustor<'input, R>(
     cu_index: Option<&'input UnitIndex<R>>,
     tu_index: Option<&'input UnitIndex<R>>,
     target_section_id: gimli::SectionId,
-) -> Box<dyn FnMut(DwarfObject, Option<Contribution>) -> Result<Option<Contribution>> + 'input>
+) -> ContributionAdjustor<'input>
 where
-    R: gimli::Reader,
+    R: gimli::Reader + 'input,
 {
-    let mut cu_adjustment = 0;
-    let mut tu_adjustment = 0;
-
     Box::new(
         move |identifier: DwarfObject,
               contribution: Option<Contribution>|
               -> Result<Option<Contribution>> {
-            let (adjustment, index) = match identifier {
-                DwarfObject::Compilation(_) => (&mut cu_adjustment, &cu_index),
-                DwarfObject::Type(_) => (&mut tu_adjustment, &tu_index),
+            let (index, index_name) = match identifier {
+                DwarfObject::Compilation(_) => (&cu_index, ".debug_cu_index"),
+                DwarfObject::Type(_) => (&tu_index, ".debug_tu_index"),
             };
             match (index, contribution) {
                 // dwp input with section
                 (Some(index), Some(contribution)) => {
                     let idx = identifier.index();
-                    let row_id = index.find(idx).ok_or(Error::UnitNotInIndex(idx))?;
                     let section = index
-                        .sections(row_id)
-                        .map_err(|e| Error::RowNotInIndex(e, row_id))?
-                        .find(|index_section| index_section.section == target_section_id)
-                        .ok_or(Error::SectionNotInRow)?;
-                    let adjusted_offset: u64 = contribution.offset.0 + *adjustment;
-                    *adjustment += section.size as u64;
-
+                        .find(idx)
+                        .ok_or(Error::UnitNotInIndex(idx))
+                        .and_then(|row_id| {
+                            index
+                                .sections(row_id)
+                                .map_err(|e| Error::RowNotInIndex(e, row_id))?
+                                .find(|index_section| index_section.section == target_section_id)
+                                .ok_or(Error::SectionNotInRow)
+                        })
+                        .map_err(|e| e.in_section(index_name, None))?;
+                    let adjusted_offset: u64 = contribution.offset.0 + section.offset as u64;
                     Ok(Some(Contribution {
                         offset: ContributionOffset(adjusted_offset),
                         size: section.size as u64,
@@ -259,83 +352,980 @@ where
     )
 }
 
-/// Wrapper around `object::write::Object` that keeps track of the section indexes relevant to
-/// DWARF packaging.
+/// Section of an input DWARF object (or package) which is copied into the output package.
+#[allow(clippy::enum_variant_names)]
+enum PreparedSection<'input> {
+    DebugAbbrev(Cow<'input, [u8]>),
+    DebugLine(Cow<'input, [u8]>),
+    DebugLoc(Cow<'input, [u8]>),
+    DebugLocLists(Cow<'input, [u8]>),
+    DebugMacinfo(Cow<'input, [u8]>),
+    DebugMacro(Cow<'input, [u8]>),
+    DebugRngLists(Cow<'input, [u8]>),
+    /// `.debug_str_offsets.dwo` is rebuilt with offsets into the output's `.debug_str.dwo`, so
+    /// only the size of the section and the ranges of the strings it references in the input's
+    /// `.debug_str.dwo` are kept.
+    DebugStrOffsets {
+        size: u64,
+        strings: Vec<Range<usize>>,
+    },
+}
+
+impl<'input> PreparedSection<'input> {
+    /// Returns the identifier of the section.
+    fn id(&self) -> gimli::SectionId {
+        match self {
+            PreparedSection::DebugAbbrev(_) => gimli::SectionId::DebugAbbrev,
+            PreparedSection::DebugLine(_) => gimli::SectionId::DebugLine,
+            PreparedSection::DebugLoc(_) => gimli::SectionId::DebugLoc,
+            PreparedSection::DebugLocLists(_) => gimli::SectionId::DebugLocLists,
+            PreparedSection::DebugMacinfo(_) => gimli::SectionId::DebugMacinfo,
+            PreparedSection::DebugMacro(_) => gimli::SectionId::DebugMacro,
+            PreparedSection::DebugRngLists(_) => gimli::SectionId::DebugRngLists,
+            PreparedSection::DebugStrOffsets { .. } => gimli::SectionId::DebugStrOffsets,
+        }
+    }
+}
+
+/// Unit in a `.debug_info.dwo` or `.debug_types.dwo` section of an input.
+#[derive(Debug)]
+struct PreparedUnit {
+    /// `DwoId` or `DebugTypeSignature` of the unit.
+    id: DwarfObject,
+    /// Range of the unit's data in its section.
+    range: Range<usize>,
+}
+
+/// `.debug_info.dwo` or `.debug_types.dwo` section of an input.
+struct PreparedUnitSection<'input> {
+    is_debug_types: bool,
+    data: Cow<'input, [u8]>,
+    units: Vec<PreparedUnit>,
+}
+
+/// DWARF contents of an input DWARF object (or package), see `PreparedInput`.
+pub(crate) struct PreparedContents<'input> {
+    /// Encoding of the first unit in the input.
+    encoding: Encoding,
+    /// Endianness of the input.
+    endian: RunTimeEndian,
+    /// `.debug_cu_index` section, if the input is a DWARF package.
+    debug_cu_index: Option<Cow<'input, [u8]>>,
+    /// `.debug_tu_index` section, if the input is a DWARF package.
+    debug_tu_index: Option<Cow<'input, [u8]>>,
+    /// `.debug_str.dwo` section, if the input has a `.debug_str_offsets.dwo` section.
+    debug_str: Option<Cow<'input, [u8]>>,
+    /// Sections which are copied into the output (and their alignment in the input), in the order
+    /// they appear in the input.
+    sections: Vec<(PreparedSection<'input>, u64)>,
+    /// Sections containing units, in the order they appear in the input.
+    unit_sections: Vec<PreparedUnitSection<'input>>,
+}
+
+/// Input DWARF object (or package) which has been read and is ready to be added to a DWARF
+/// package.
+///
+/// Preparing an input decompresses its sections, reads the strings referenced by its string
+/// offsets and finds its units - none of which depends on the DWARF package being created, so
+/// inputs can be prepared in parallel. Adding prepared inputs to the package happens in order.
+pub(crate) struct PreparedInput<'input> {
+    /// Format of the input object.
+    pub(crate) format: BinaryFormat,
+    /// Architecture of the input object.
+    pub(crate) architecture: object::Architecture,
+    /// Endianness of the input object.
+    pub(crate) endianness: object::Endianness,
+    /// DWARF contents of the input object, `None` if the input doesn't have a `.debug_info.dwo`
+    /// section.
+    pub(crate) contents: Option<PreparedContents<'input>>,
+    /// Name of the archive member containing the input object, if it is in an archive.
+    pub(crate) member: Option<String>,
+}
+
+/// Sections of an input used to read the debugging information entries of one of its units, see
+/// `PreparedContents::unit_sections`.
+pub(crate) struct UnitSections<'input> {
+    /// Endianness of the input.
+    endian: RunTimeEndian,
+    /// Contribution of the unit to the input's `.debug_abbrev.dwo` section, and any data
+    /// following it.
+    debug_abbrev: &'input [u8],
+    /// `.debug_str.dwo` section of the input.
+    debug_str: &'input [u8],
+    /// Ranges of the strings in `.debug_str.dwo` referenced by the unit's contribution to the
+    /// input's `.debug_str_offsets.dwo` section (and any following contributions), in the order of
+    /// the offsets.
+    strings: &'input [Range<usize>],
+}
+
+impl<'input> UnitSections<'input> {
+    /// Returns the header of the unit at the start of `data`, which is from a `.debug_types.dwo`
+    /// section if `is_debug_types` and a `.debug_info.dwo` section otherwise.
+    pub(crate) fn read_header<'data>(
+        &self,
+        data: &'data [u8],
+        is_debug_types: bool,
+    ) -> Result<UnitHeader<gimli::EndianSlice<'data, RunTimeEndian>>> {
+        let mut iter = if is_debug_types {
+            UnitHeaderIterator::DebugTypes(gimli::DebugTypes::new(data, self.endian).units())
+        } else {
+            UnitHeaderIterator::DebugInfo(gimli::DebugInfo::new(data, self.endian).units())
+        };
+        iter.next().map_err(Error::ParseUnitHeader)?.ok_or(Error::NoDie)
+    }
+
+    /// Returns the unit's contribution to the input's `.debug_abbrev.dwo` section.
+    pub(crate) fn debug_abbrev(
+        &self,
+    ) -> gimli::DebugAbbrev<gimli::EndianSlice<'input, RunTimeEndian>> {
+        gimli::DebugAbbrev::new(self.debug_abbrev, self.endian)
+    }
+
+    /// Returns the string of an attribute value, reading it from the input's `.debug_str.dwo`
+    /// section if necessary, or `None` if the value isn't a string.
+    pub(crate) fn string<'data>(
+        &self,
+        value: gimli::AttributeValue<gimli::EndianSlice<'data, RunTimeEndian>>,
+    ) -> Result<Option<&'data [u8]>>
+    where
+        'input: 'data,
+    {
+        let string = match value {
+            gimli::AttributeValue::String(string) => string.slice(),
+            gimli::AttributeValue::DebugStrRef(offset) => {
+                gimli::DebugStr::new(self.debug_str, self.endian)
+                    .get_str(offset)
+                    .map_err(|e| Error::StrAtOffset(e, offset.0))?
+                    .slice()
+            }
+            gimli::AttributeValue::DebugStrOffsetsIndex(index) => self
+                .strings
+                .get(index.0)
+                .and_then(|range| self.debug_str.get(range.clone()))
+                .ok_or(Error::OffsetAtIndex(gimli::Error::OffsetOutOfBounds, index.0 as u64))?,
+            _ => return Ok(None),
+        };
+        Ok(Some(string))
+    }
+}
+
+impl<'input> PreparedContents<'input> {
+    /// Returns the `DwoId`s of the compilation units in the input.
+    pub(crate) fn compilation_units(&self) -> impl Iterator<Item = DwoId> + '_ {
+        self.units().filter_map(|id| match id {
+            DwarfObject::Compilation(dwo_id) => Some(dwo_id),
+            DwarfObject::Type(_) => None,
+        })
+    }
+
+    /// Returns the identifiers of the units in the input.
+    pub(crate) fn units(&self) -> impl Iterator<Item = DwarfObject> + '_ {
+        self.unit_sections.iter().flat_map(|section| &section.units).map(|unit| unit.id)
+    }
+
+    /// Returns the ranges of the strings referenced by each `.debug_str_offsets.dwo` section of the
+    /// input, for use with `unit_sections`.
+    fn strings(&self) -> Vec<Range<usize>> {
+        self.sections
+            .iter()
+            .filter_map(|(section, _)| match section {
+                PreparedSection::DebugStrOffsets { strings, .. } => Some(strings),
+                _ => None,
+            })
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the sections of the input used to read the debugging information entries of the
+    /// unit `id`, using the input's indexes `cu_index` and `tu_index` (if the input is a DWARF
+    /// package) and the input's `strings`.
+    fn unit_sections<'a, R: gimli::Reader>(
+        &'a self,
+        strings: &'a [Range<usize>],
+        cu_index: Option<&UnitIndex<R>>,
+        tu_index: Option<&UnitIndex<R>>,
+        id: DwarfObject,
+    ) -> Result<UnitSections<'a>> {
+        let index = match id {
+            DwarfObject::Compilation(_) => cu_index,
+            DwarfObject::Type(_) => tu_index,
+        };
+        let debug_abbrev = self
+            .sections
+            .iter()
+            .find_map(|(section, _)| match section {
+                PreparedSection::DebugAbbrev(data) => Some(data.as_ref()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let abbrev_offset = input_contribution_offset(index, id, gimli::SectionId::DebugAbbrev)?;
+        let str_offsets_offset =
+            input_contribution_offset(index, id, gimli::SectionId::DebugStrOffsets)?;
+        let entry_size = usize::from(self.encoding.format.word_size());
+        Ok(UnitSections {
+            endian: self.endian,
+            debug_abbrev: debug_abbrev.get(abbrev_offset..).unwrap_or_default(),
+            debug_str: self.debug_str.as_deref().unwrap_or_default(),
+            strings: strings.get(str_offsets_offset / entry_size..).unwrap_or_default(),
+        })
+    }
+
+    /// Returns the units of the input which are selected by `filter`: compilation units selected
+    /// by their `DwoId` or name, and the type units which are referenced by selected units.
+    pub(crate) fn selected_units(&self, filter: &UnitFilter) -> Result<HashSet<DwarfObject>> {
+        let reader = UnitEntriesReader::new(self)?;
+        let mut roots = Vec::new();
+        for dwo_id in self.compilation_units() {
+            let id = DwarfObject::Compilation(dwo_id);
+            let selected = reader.read_unit(id, |data, is_debug_types, sections| {
+                Ok(filter.selects(dwo_id, unit_name(data, is_debug_types, sections)?))
+            })?;
+            if selected == Some(true) {
+                roots.push(id);
+            }
+        }
+
+        reader.with_referenced_type_units(roots)
+    }
+
+    /// Returns the total size of the sections of the input.
+    fn size(&self) -> u64 {
+        let sections = self.sections.iter().map(|(section, _)| match section {
+            PreparedSection::DebugAbbrev(data)
+            | PreparedSection::DebugLine(data)
+            | PreparedSection::DebugLoc(data)
+            | PreparedSection::DebugLocLists(data)
+            | PreparedSection::DebugMacinfo(data)
+            | PreparedSection::DebugMacro(data)
+            | PreparedSection::DebugRngLists(data) => data.len() as u64,
+            PreparedSection::DebugStrOffsets { size, .. } => *size,
+        });
+        let unit_sections = self.unit_sections.iter().map(|section| section.data.len() as u64);
+        let debug_str = self.debug_str.as_ref().map_or(0, |data| data.len() as u64);
+        sections.chain(unit_sections).sum::<u64>() + debug_str
+    }
+
+    /// Returns the units of the input which aren't in `skipped_units`, grouped so that each group
+    /// can be added to a different shard of a sharded DWARF package.
+    ///
+    /// Each compilation unit of a DWARF package is in its own group with the type units it
+    /// references (so type units can be in more than one group), and type units which aren't
+    /// referenced by any compilation unit are in the first group. The units of other inputs are
+    /// in a single group.
+    pub(crate) fn unit_groups(
+        &self,
+        skipped_units: &HashSet<DwarfObject>,
+    ) -> Result<Vec<UnitGroup>> {
+        let units: Vec<_> = self.units().filter(|id| !skipped_units.contains(id)).collect();
+        let compilation_units: Vec<_> = units
+            .iter()
+            .filter_map(|id| match id {
+                DwarfObject::Compilation(dwo_id) => Some(*dwo_id),
+                DwarfObject::Type(_) => None,
+            })
+            .collect();
+        if self.debug_cu_index.is_none() || compilation_units.len() <= 1 {
+            return Ok(vec![UnitGroup {
+                compilation_unit: compilation_units.first().copied(),
+                units: units.into_iter().collect(),
+                size: self.size(),
+            }]);
+        }
+
+        let reader = UnitEntriesReader::new(self)?;
+        let mut groups = Vec::with_capacity(compilation_units.len());
+        let mut grouped = HashSet::new();
+        for dwo_id in compilation_units {
+            let mut group =
+                UnitGroup { compilation_unit: Some(dwo_id), units: HashSet::new(), size: 0 };
+            for id in reader.with_referenced_type_units(vec![DwarfObject::Compilation(dwo_id)])? {
+                if !skipped_units.contains(&id) {
+                    group.size += reader.contributions_size(id)?;
+                    group.units.insert(id);
+                    grouped.insert(id);
+                }
+            }
+            groups.push(group);
+        }
+
+        let first = &mut groups[0];
+        for id in units.into_iter().filter(|id| !grouped.contains(id)) {
+            first.size += reader.contributions_size(id)?;
+            first.units.insert(id);
+        }
+
+        Ok(groups)
+    }
+}
+
+/// Units of an input which are added to the same shard of a sharded DWARF package, see
+/// `PreparedContents::unit_groups`.
+pub(crate) struct UnitGroup {
+    /// Compilation unit of the group, `None` if the group only has type units.
+    pub(crate) compilation_unit: Option<DwoId>,
+    /// Units in the group.
+    pub(crate) units: HashSet<DwarfObject>,
+    /// Size of the group's contributions to the sections of the input.
+    pub(crate) size: u64,
+}
+
+/// Reads the debugging information entries of the units of a prepared input, see
+/// `PreparedContents::selected_units` and `PreparedContents::unit_groups`.
+struct UnitEntriesReader<'a, 'input> {
+    contents: &'a PreparedContents<'input>,
+    /// Index sections of the input, if it is a DWARF package.
+    cu_index: Option<UnitIndex<gimli::EndianSlice<'a, RunTimeEndian>>>,
+    tu_index: Option<UnitIndex<gimli::EndianSlice<'a, RunTimeEndian>>>,
+    /// Strings referenced by the input, see `PreparedContents::strings`.
+    strings: Vec<Range<usize>>,
+    /// Section containing each unit of the input, and the unit.
+    units: HashMap<DwarfObject, (&'a PreparedUnitSection<'input>, &'a PreparedUnit)>,
+}
+
+impl<'a, 'input> UnitEntriesReader<'a, 'input> {
+    fn new(contents: &'a PreparedContents<'input>) -> Result<Self> {
+        let cu_index = maybe_load_index_section::<_, gimli::DebugCuIndex<_>, _>(
+            contents.encoding,
+            contents.endian,
+            contents.debug_cu_index.as_deref(),
+        )?;
+        let tu_index = maybe_load_index_section::<_, gimli::DebugTuIndex<_>, _>(
+            contents.encoding,
+            contents.endian,
+            contents.debug_tu_index.as_deref(),
+        )?;
+
+        let mut units = HashMap::new();
+        for section in &contents.unit_sections {
+            for unit in &section.units {
+                units.insert(unit.id, (section, unit));
+            }
+        }
+
+        Ok(Self { contents, cu_index, tu_index, strings: contents.strings(), units })
+    }
+
+    /// Returns the result of calling `f` with the data of the unit `id`, whether the unit is from
+    /// a `.debug_types.dwo` section and the sections used to read it, or `None` if the input
+    /// doesn't contain the unit. Errors are located in the unit's section.
+    fn read_unit<T>(
+        &self,
+        id: DwarfObject,
+        f: impl FnOnce(&[u8], bool, &UnitSections<'_>) -> Result<T>,
+    ) -> Result<Option<T>> {
+        let (section, unit) = match self.units.get(&id) {
+            Some(unit) => unit,
+            None => return Ok(None),
+        };
+        let section_name =
+            if section.is_debug_types { ".debug_types.dwo" } else { ".debug_info.dwo" };
+        let sections = self.contents.unit_sections(
+            &self.strings,
+            self.cu_index.as_ref(),
+            self.tu_index.as_ref(),
+            id,
+        )?;
+        f(&section.data[unit.range.clone()], section.is_debug_types, &sections)
+            .map(Some)
+            .map_err(|e| e.in_section(section_name, Some(unit.range.start as u64)))
+    }
+
+    /// Returns the units in `roots` which are in the input and the type units of the input which
+    /// are referenced by them, directly or through other type units.
+    fn with_referenced_type_units(&self, roots: Vec<DwarfObject>) -> Result<HashSet<DwarfObject>> {
+        let mut units = HashSet::new();
+        let mut worklist = roots;
+        while let Some(id) = worklist.pop() {
+            if !self.units.contains_key(&id) || !units.insert(id) {
+                continue;
+            }
+
+            let signatures = self.read_unit(id, referenced_type_units)?.unwrap_or_default();
+            worklist.extend(signatures.into_iter().map(DwarfObject::Type));
+        }
+
+        Ok(units)
+    }
+
+    /// Returns the total size of the contributions of the unit `id` to the sections of the input,
+    /// which must be a DWARF package.
+    fn contributions_size(&self, id: DwarfObject) -> Result<u64> {
+        let index = match id {
+            DwarfObject::Compilation(_) => self.cu_index.as_ref(),
+            DwarfObject::Type(_) => self.tu_index.as_ref(),
+        };
+        let index = match index {
+            Some(index) => index,
+            None => return Ok(0),
+        };
+
+        let idx = id.index();
+        let row_id = index.find(idx).ok_or(Error::UnitNotInIndex(idx))?;
+        let sections = index.sections(row_id).map_err(|e| Error::RowNotInIndex(e, row_id))?;
+        Ok(sections.map(|section| u64::from(section.size)).sum())
+    }
+}
+
+impl<'input> PreparedInput<'input> {
+    /// Read an input DWARF object (or package), decompressing its sections, reading the strings
+    /// referenced by its string offsets and finding its units.
+    #[tracing::instrument(level = "trace", skip(input))]
+    pub(crate) fn new(input: &object::File<'input>) -> Result<Self> {
+        let mut prepared = PreparedInput {
+            format: input.format(),
+            architecture: input.architecture(),
+            endianness: input.endianness(),
+            contents: None,
+            member: None,
+        };
+        let endian = input.endianness().as_runtime_endian();
+
+        let encoding = if let Some(section) = dwo_section_by_id(input, gimli::SectionId::DebugInfo)?
+        {
+            let data = decompress_section(&section)?;
+            let debug_info = gimli::DebugInfo::new(&data, endian);
+            debug_info
+                .units()
+                .next()
+                .map_err(Error::ParseUnitHeader)
+                .and_then(|header| header.ok_or(Error::NoCompilationUnits))
+                .map(|root_header| root_header.encoding())
+                .map_err(|e| e.in_section(".debug_info.dwo", Some(0)))?
+        } else {
+            debug!("no `.debug_info.dwo` in input dwarf object");
+            return Ok(prepared);
+        };
+
+        let decompress_section_by_id = |id| -> Result<_> {
+            match dwo_section_by_id(input, id)? {
+                Some(section) => Ok(Some(decompress_section(&section)?)),
+                None => Ok(None),
+            }
+        };
+
+        // Decompress index sections (if they exist) and check that they can be loaded, the
+        // indexes are loaded again when the input is added to the package.
+        let debug_cu_index = decompress_section_by_id(gimli::SectionId::DebugCuIndex)?;
+        let cu_index = maybe_load_index_section::<_, gimli::DebugCuIndex<_>, _>(
+            encoding,
+            endian,
+            debug_cu_index.as_deref(),
+        )?;
+        let abbrev_offsets = abbrev_contribution_offsets(cu_index.as_ref())
+            .map_err(|e| e.in_section(".debug_cu_index", None))?;
+        let debug_tu_index = decompress_section_by_id(gimli::SectionId::DebugTuIndex)?;
+        maybe_load_index_section::<_, gimli::DebugTuIndex<_>, _>(
+            encoding,
+            endian,
+            debug_tu_index.as_deref(),
+        )?;
+
+        let mut debug_str = None;
+        let mut sections = Vec::new();
+
+        // Iterate over sections rather than using `dwo_section_by_id` because sections can be
+        // repeated.
+        for section in input.sections() {
+            let name = section.name().map_err(Error::NonUtf8SectionName)?;
+            let prepared_section = match gimli::SectionId::from_dwo_section(input, &section)? {
+                Some(gimli::SectionId::DebugAbbrev) => {
+                    PreparedSection::DebugAbbrev(decompress_section(&section)?)
+                }
+                Some(gimli::SectionId::DebugLine) => {
+                    PreparedSection::DebugLine(decompress_section(&section)?)
+                }
+                Some(gimli::SectionId::DebugLoc) => {
+                    PreparedSection::DebugLoc(decompress_section(&section)?)
+                }
+                Some(gimli::SectionId::DebugLocLists) => {
+                    PreparedSection::DebugLocLists(decompress_section(&section)?)
+                }
+                Some(gimli::SectionId::DebugMacinfo) => {
+                    PreparedSection::DebugMacinfo(decompress_section(&section)?)
+                }
+                Some(gimli::SectionId::DebugMacro) => {
+                    PreparedSection::DebugMacro(decompress_section(&section)?)
+                }
+                Some(gimli::SectionId::DebugRngLists) => {
+                    PreparedSection::DebugRngLists(decompress_section(&section)?)
+                }
+                Some(gimli::SectionId::DebugStrOffsets) => {
+                    let data = decompress_section(&section)?;
+                    let debug_str_offsets_section =
+                        gimli::DebugStrOffsets::from(gimli::EndianSlice::new(&data, endian));
+
+                    if debug_str.is_none() {
+                        debug_str = Some(
+                            decompress_section_by_id(gimli::SectionId::DebugStr)?
+                                .ok_or(Error::MissingRequiredSection(".debug_str.dwo"))?,
+                        );
+                    }
+                    let debug_str_section = gimli::DebugStr::new(
+                        debug_str.as_deref().expect("`.debug_str.dwo` w/out data"),
+                        endian,
+                    );
+
+                    let size = data.len().try_into().expect("section size larger than u64");
+                    let strings = read_str_offsets_section(
+                        debug_str_section,
+                        debug_str_offsets_section,
+                        size,
+                        encoding,
+                    )
+                    .map_err(|e| e.in_section(name, None))?;
+                    PreparedSection::DebugStrOffsets { size, strings }
+                }
+                _ => continue,
+            };
+            sections.push((prepared_section, uncompressed_section_align(input, &section)));
+        }
+
+        // `.debug_abbrev.dwo` will already have been prepared, but getting the `DwoId` of a GNU
+        // Extension compilation unit requires access to it.
+        let debug_abbrev = decompress_section_by_id(gimli::SectionId::DebugAbbrev)?
+            .ok_or(Error::MissingRequiredSection(".debug_abbrev.dwo"))?;
+
+        let mut unit_sections = Vec::new();
+        let mut seen_debug_info = false;
+        let mut seen_debug_types = false;
+
+        for section in input.sections() {
+            let name = section.name().map_err(Error::NonUtf8SectionName)?;
+            let is_debug_types = match gimli::SectionId::from_dwo_section(input, &section)? {
+                Some(gimli::SectionId::DebugInfo)
+                    // Report an error if a input DWARF package has multiple `.debug_info`
+                    // sections.
+                    if seen_debug_info && debug_cu_index.is_some() =>
+                {
+                    return Err(Error::MultipleDebugInfoSection.in_section(name, None));
+                }
+                Some(gimli::SectionId::DebugInfo) => {
+                    seen_debug_info = true;
+                    false
+                }
+                Some(gimli::SectionId::DebugTypes)
+                    // Report an error if a input DWARF package has multiple `.debug_types`
+                    // sections.
+                    if seen_debug_types && debug_tu_index.is_some() =>
+                {
+                    return Err(Error::MultipleDebugTypesSection.in_section(name, None));
+                }
+                Some(gimli::SectionId::DebugTypes) => {
+                    seen_debug_types = true;
+                    true
+                }
+                _ => continue,
+            };
+
+            let data = decompress_section(&section)?;
+            let units = Self::find_units(
+                &debug_abbrev,
+                &abbrev_offsets,
+                &data,
+                endian,
+                is_debug_types,
+                name,
+            )?;
+            unit_sections.push(PreparedUnitSection { is_debug_types, data, units });
+        }
+
+        prepared.contents = Some(PreparedContents {
+            encoding,
+            endian,
+            debug_cu_index,
+            debug_tu_index,
+            debug_str,
+            sections,
+            unit_sections,
+        });
+        Ok(prepared)
+    }
+
+    /// Returns the units in a `.debug_info.dwo` or `.debug_types.dwo` section named `name`.
+    /// `abbrev_offsets` has the offset of the `.debug_abbrev.dwo` contribution of each
+    /// compilation unit of a DWARF package, by the offset of the unit (see
+    /// `abbrev_contribution_offsets`).
+    fn find_units(
+        debug_abbrev: &[u8],
+        abbrev_offsets: &HashMap<usize, usize>,
+        data: &[u8],
+        endian: RunTimeEndian,
+        is_debug_types: bool,
+        name: &str,
+    ) -> Result<Vec<PreparedUnit>> {
+        let mut iter = if is_debug_types {
+            UnitHeaderIterator::DebugTypes(gimli::DebugTypes::new(data, endian).units())
+        } else {
+            UnitHeaderIterator::DebugInfo(gimli::DebugInfo::new(data, endian).units())
+        };
+
+        // Errors are reported at the offset of the unit being read, which (for errors reading unit
+        // headers) is the end of the previous unit.
+        let mut units = Vec::new();
+        let mut next_offset = 0;
+        while let Some(header) = iter
+            .next()
+            .map_err(|e| Error::ParseUnitHeader(e).in_section(name, Some(next_offset as u64)))?
+        {
+            let size = header.length_including_self();
+            let offset = match header.offset() {
+                UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+                UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+            };
+            let in_unit = |e: Error| e.in_section(name, Some(offset as u64));
+
+            // Abbreviation offsets of units in DWARF packages are relative to the unit's
+            // contribution to `.debug_abbrev.dwo`.
+            let abbrev_offset = if is_debug_types { None } else { abbrev_offsets.get(&offset) };
+            let debug_abbrev = match abbrev_offset {
+                Some(&abbrev_offset) => debug_abbrev
+                    .get(abbrev_offset..)
+                    .ok_or(gimli::Error::OffsetOutOfBounds)
+                    .map_err(|e| in_unit(e.into()))?,
+                None => debug_abbrev,
+            };
+            let debug_abbrev = gimli::DebugAbbrev::new(debug_abbrev, endian);
+
+            let id = match dwo_identifier_of_unit(&debug_abbrev, &header).map_err(in_unit)? {
+                Some(id) => id,
+                // Report an error if the unit doesn't have a `DwoId` or `DebugTypeSignature`.
+                None => return Err(in_unit(Error::NotSplitUnit)),
+            };
+
+            let range = offset..offset + size;
+            if data.get(range.clone()).is_none() {
+                return Err(in_unit(Error::EmptyUnit(id.index())));
+            }
+
+            next_offset = range.end;
+            units.push(PreparedUnit { id, range });
+        }
+
+        Ok(units)
+    }
+}
+
+/// Identifier for a section of an `OutputObject`.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+enum OutputSectionId {
+    InMemory(SectionId),
+    Streaming(StreamingSectionId),
+}
+
+/// Object file of a DWARF package, either accumulated in memory or streamed to temporary files.
+pub(crate) enum OutputObject<'file> {
+    /// Sections are accumulated in an `object::write::Object`.
+    InMemory(WritableObject<'file>),
+    /// Sections are written to temporary files as they are appended.
+    Streaming(StreamingObject),
+}
+
+impl<'file> OutputObject<'file> {
+    /// Add a new section of kind `kind` (either a debugging section or a note section) to the
+    /// object.
+    fn add_section(
+        &mut self,
+        segment: Vec<u8>,
+        name: Vec<u8>,
+        kind: SectionKind,
+    ) -> Result<OutputSectionId> {
+        match self {
+            OutputObject::InMemory(obj) => {
+                Ok(OutputSectionId::InMemory(obj.add_section(segment, name, kind)))
+            }
+            OutputObject::Streaming(obj) => {
+                let sh_type = match kind {
+                    SectionKind::Note => elf::SHT_NOTE,
+                    _ => elf::SHT_PROGBITS,
+                };
+                Ok(OutputSectionId::Streaming(obj.add_section(name, sh_type)?))
+            }
+        }
+    }
+
+    /// Append data to a section, padding the section so that the data is aligned to `align`,
+    /// returning the offset of the data in the section.
+    fn append_section_data(&mut self, id: OutputSectionId, data: &[u8], align: u64) -> Result<u64> {
+        match (self, id) {
+            (OutputObject::InMemory(obj), OutputSectionId::InMemory(id)) => {
+                Ok(obj.append_section_data(id, data, align))
+            }
+            (OutputObject::Streaming(obj), OutputSectionId::Streaming(id)) => {
+                obj.append_section_data(id, data, align)
+            }
+            _ => unreachable!("section identifier from a different kind of output object"),
+        }
+    }
+
+    /// Compress the sections `sections` (with the alignment of their contents) with
+    /// `compression`, making them `SHF_COMPRESSED` sections. `sections` must contain every section
+    /// of the object.
+    fn compress_sections(
+        self,
+        sections: &[(OutputSectionId, u64, bool)],
+        compression: OutputCompression,
+        endianness: object::Endianness,
+        is_64: bool,
+    ) -> Result<Self> {
+        match self {
+            // Data of sections in an `object::write::Object` can't be replaced, so create a new
+            // object with the same sections in the same order.
+            OutputObject::InMemory(obj) => {
+                let mut compressed =
+                    WritableObject::new(obj.format(), obj.architecture(), endianness);
+                let mut sections = sections.to_vec();
+                sections.sort();
+                for (id, align, compress) in sections {
+                    let section = match id {
+                        OutputSectionId::InMemory(id) => obj.section(id),
+                        _ => unreachable!(
+                            "section identifier from a different kind of output object"
+                        ),
+                    };
+                    let segment = section.segment().unwrap_or_default().as_bytes().to_vec();
+                    let name = section.name().unwrap_or_default().as_bytes().to_vec();
+                    let id = compressed.add_section(segment, name, SectionKind::Debug);
+                    if compress {
+                        let data = compress_section(
+                            compression,
+                            endianness,
+                            is_64,
+                            section.data().len() as u64,
+                            align,
+                            section.data(),
+                            Vec::new(),
+                        )
+                        .map_err(Error::CompressSection)?;
+                        let section = compressed.section_mut(id);
+                        section.set_data(data, compressed_section_align(is_64));
+                        section.flags = SectionFlags::Elf { sh_flags: elf::SHF_COMPRESSED.into() };
+                    } else {
+                        compressed.section_mut(id).set_data(section.data().to_vec(), align);
+                    }
+                }
+                Ok(OutputObject::InMemory(compressed))
+            }
+            OutputObject::Streaming(mut obj) => {
+                for (id, _, compress) in sections {
+                    match (id, compress) {
+                        (OutputSectionId::Streaming(id), true) => {
+                            obj.compress_section(*id, compression, is_64)?
+                        }
+                        (OutputSectionId::Streaming(_), false) => (),
+                        _ => unreachable!(
+                            "section identifier from a different kind of output object"
+                        ),
+                    }
+                }
+                Ok(OutputObject::Streaming(obj))
+            }
+        }
+    }
+
+    /// Returns the object file as an `object::write::Object`, reading the contents of sections
+    /// from temporary files into memory if the object is being streamed.
+    pub(crate) fn into_object(self) -> Result<WritableObject<'file>> {
+        match self {
+            OutputObject::InMemory(obj) => Ok(obj),
+            OutputObject::Streaming(obj) => obj.into_object(),
+        }
+    }
+
+    /// Write the object file to `output`.
+    pub(crate) fn write<W: io::Write>(self, output: W) -> Result<()> {
+        match self {
+            OutputObject::InMemory(obj) => {
+                let mut buffer = StreamingBuffer::new(output);
+                obj.emit(&mut buffer).map_err(Error::EmitOutputObject)?;
+                buffer.result().map_err(Error::WriteOutput)
+            }
+            OutputObject::Streaming(obj) => obj.write(output),
+        }
+    }
+}
+
+/// Wrapper around `OutputObject` that keeps track of the section indexes relevant to DWARF
+/// packaging.
 struct DwarfPackageObject<'file> {
     /// Object file being created.
-    obj: WritableObject<'file>,
+    obj: OutputObject<'file>,
+    /// Format of the object file being created, determines the names of sections.
+    format: OutputFormat,
+    /// Endianness of the object file being created.
+    endianness: object::Endianness,
+    /// Whether the object file being created is 64-bit, determines the compression header of
+    /// compressed sections.
+    is_64: bool,
+    /// Compression of the sections of the object file being created, if any.
+    compression: Option<OutputCompression>,
+    /// Whether `.debug_cu_index` and `.debug_tu_index` are compressed with the other sections.
+    compress_index_sections: bool,
 
     /// Identifier for output `.debug_cu_index.dwo` section.
-    debug_cu_index: Option<SectionId>,
+    debug_cu_index: Option<OutputSectionId>,
     /// `.debug_tu_index.dwo`
-    debug_tu_index: Option<SectionId>,
+    debug_tu_index: Option<OutputSectionId>,
     /// `.debug_info.dwo`
-    debug_info: Option<SectionId>,
+    debug_info: Option<OutputSectionId>,
     /// `.debug_abbrev.dwo`
-    debug_abbrev: Option<SectionId>,
+    debug_abbrev: Option<OutputSectionId>,
     /// `.debug_str.dwo`
-    debug_str: Option<SectionId>,
+    debug_str: Option<OutputSectionId>,
     /// `.debug_types.dwo`
-    debug_types: Option<SectionId>,
+    debug_types: Option<OutputSectionId>,
     /// `.debug_line.dwo`
-    debug_line: Option<SectionId>,
+    debug_line: Option<OutputSectionId>,
     /// `.debug_loc.dwo`
-    debug_loc: Option<SectionId>,
+    debug_loc: Option<OutputSectionId>,
     /// `.debug_loclists.dwo`
-    debug_loclists: Option<SectionId>,
+    debug_loclists: Option<OutputSectionId>,
     /// `.debug_rnglists.dwo`
-    debug_rnglists: Option<SectionId>,
+    debug_rnglists: Option<OutputSectionId>,
     /// `.debug_str_offsets.dwo`
-    debug_str_offsets: Option<SectionId>,
+    debug_str_offsets: Option<OutputSectionId>,
     /// `.debug_macinfo.dwo`
-    debug_macinfo: Option<SectionId>,
+    debug_macinfo: Option<OutputSectionId>,
     /// `.debug_macro.dwo`
-    debug_macro: Option<SectionId>,
+    debug_macro: Option<OutputSectionId>,
+    /// Alignment of each section, the greatest alignment of any contribution to the section.
+    alignments: HashMap<OutputSectionId, u64>,
+    /// Name and size of each section, in the order the sections were created.
+    sizes: IndexMap<OutputSectionId, (String, u64)>,
+}
+
+/// Alignment of `.debug_cu_index` and `.debug_tu_index`, which contain 64-bit signatures.
+const INDEX_SECTION_ALIGN: u64 = 8;
+
+/// Returns the alignment of a contribution to the output section `section` from an input section
+/// with alignment `input_align`, containing units with the DWARF format `format`.
+///
+/// Units and tables in `.debug_info.dwo`, `.debug_types.dwo`, `.debug_line.dwo`,
+/// `.debug_loclists.dwo`, `.debug_rnglists.dwo` and `.debug_macro.dwo` are read sequentially by
+/// some consumers, which would read any padding as the start of another unit or table, so
+/// contributions to these sections are never padded. Contributions to other sections keep the
+/// alignment of their input section, and entries in `.debug_str_offsets.dwo` are also aligned to
+/// the size of an offset.
+fn contribution_align(section: gimli::SectionId, format: gimli::Format, input_align: u64) -> u64 {
+    // Alignment of sections in object files should be a power of two, but don't trust inputs.
+    let input_align = input_align.max(1).next_power_of_two();
+    match section {
+        gimli::SectionId::DebugInfo
+        | gimli::SectionId::DebugTypes
+        | gimli::SectionId::DebugLine
+        | gimli::SectionId::DebugLocLists
+        | gimli::SectionId::DebugRngLists
+        | gimli::SectionId::DebugMacro => 1,
+        gimli::SectionId::DebugStrOffsets => input_align.max(format.word_size().into()),
+        _ => input_align,
+    }
+}
+
+/// Alignment of notes in ELF note sections (and of the sections themselves).
+const NOTE_ALIGN: u64 = 4;
+
+/// Returns the contents of a `.note.gnu.build-id` section containing an `NT_GNU_BUILD_ID` note for
+/// each of `build_ids`.
+fn build_id_notes(
+    endian: RunTimeEndian,
+    build_ids: &[Vec<u8>],
+) -> Result<EndianVec<RunTimeEndian>> {
+    // Names and descriptors of notes are padded to `NOTE_ALIGN`, and names are null-terminated.
+    let align = NOTE_ALIGN as usize;
+    let padding = |size: usize| vec![0; (align - size % align) % align];
+    let name_size = elf::ELF_NOTE_GNU.len() + 1;
+
+    let mut out = EndianVec::new(endian);
+    for build_id in build_ids {
+        // Build IDs are read from notes, so their size always fits in a note's `n_descsz`.
+        out.write_u32(name_size as u32)?;
+        out.write_u32(build_id.len() as u32)?;
+        out.write_u32(elf::NT_GNU_BUILD_ID)?;
+        out.write(elf::ELF_NOTE_GNU)?;
+        out.write(&[0])?;
+        out.write(&padding(name_size))?;
+        out.write(build_id)?;
+        out.write(&padding(build_id.len()))?;
+    }
+
+    Ok(out)
 }
 
 /// Macro for generating helper functions which appending non-empty data to specific sections.
 macro_rules! generate_append_for {
     ( $( $fn_name:ident => ($name:ident, $section_name:expr) ),+ ) => {
         $(
-            fn $fn_name(&mut self, data: &[u8]) -> Option<Contribution> {
+            fn $fn_name(&mut self, data: &[u8], align: u64) -> Result<Option<Contribution>> {
                 if data.is_empty() {
-                    return None;
+                    return Ok(None);
                 }
 
                 let id = if self.$name.is_none() {
-                    let id = self.obj.add_section(
-                        Vec::new(),
-                        Vec::from($section_name),
-                        SectionKind::Debug,
-                    );
+                    let (segment, name) = self.format.section_name($section_name);
+                    let size = (String::from_utf8_lossy(&name).into_owned(), 0);
+                    let id = self.obj.add_section(segment, name, SectionKind::Debug)?;
+                    self.sizes.insert(id, size);
                     self.$name = Some(id);
                     id
                 } else {
                     self.$name.expect("`generate_append_for` is broken")
                 };
 
-                // FIXME: correct alignment
-                let offset = self.obj.append_section_data(id, data, 1);
+                let offset = self.obj.append_section_data(id, data, align)?;
+                let section_align = self.alignments.entry(id).or_insert(1);
+                *section_align = (*section_align).max(align);
                 debug!(?offset, ?data);
-                Some(Contribution {
-                    offset: ContributionOffset(offset),
-                    size: data.len().try_into().expect("data size larger than u64"),
-                })
+                let size = data.len().try_into().expect("data size larger than u64");
+                if let Some((_, section_size)) = self.sizes.get_mut(&id) {
+                    *section_size = offset + size;
+                }
+                Ok(Some(Contribution { offset: ContributionOffset(offset), size }))
             }
         )+
     };
 }
 
 impl<'file> DwarfPackageObject<'file> {
-    /// Create a new `DwarfPackageObject` from an architecture and endianness.
+    /// Create a new `DwarfPackageObject` from a format, architecture and endianness, streaming
+    /// sections to temporary files in `streaming_dir` if provided.
     #[tracing::instrument(level = "trace")]
     pub(crate) fn new(
+        format: OutputFormat,
         architecture: object::Architecture,
         endianness: object::Endianness,
-    ) -> DwarfPackageObject<'file> {
-        let obj = WritableObject::new(BinaryFormat::Elf, architecture, endianness);
-        Self {
+        streaming_dir: Option<&Path>,
+        compression: Option<OutputCompression>,
+        compress_index_sections: bool,
+    ) -> Result<DwarfPackageObject<'file>> {
+        let is_64 = match architecture.address_size() {
+            Some(address_size) => address_size == object::AddressSize::U64,
+            None if compression.is_some() => {
+                return Err(Error::UnsupportedCompressedOutputArchitecture(architecture));
+            }
+            None => false,
+        };
+        if compression.is_some() && format != OutputFormat::Elf {
+            return Err(Error::UnsupportedCompressedOutputFormat);
+        }
+
+        let obj = match streaming_dir {
+            Some(dir) if format == OutputFormat::Elf => {
+                OutputObject::Streaming(StreamingObject::new(dir, architecture, endianness)?)
+            }
+            Some(_) => return Err(Error::UnsupportedStreamingOutputFormat),
+            None => OutputObject::InMemory(WritableObject::new(
+                format.binary_format(),
+                architecture,
+                endianness,
+            )),
+        };
+        Ok(Self {
             obj,
+            format,
+            endianness,
+            is_64,
+            compression,
+            compress_index_sections,
             debug_cu_index: Default::default(),
             debug_tu_index: Default::default(),
             debug_info: Default::default(),
@@ -349,7 +1339,9 @@ impl<'file> DwarfPackageObject<'file> {
             debug_str_offsets: Default::default(),
             debug_macinfo: Default::default(),
             debug_macro: Default::default(),
-        }
+            alignments: Default::default(),
+            sizes: Default::default(),
+        })
     }
 
     generate_append_for! {
@@ -368,9 +1360,60 @@ impl<'file> DwarfPackageObject<'file> {
         append_to_debug_types => (debug_types, ".debug_types.dwo")
     }
 
-    /// Return the DWARF package object file.
-    pub(crate) fn finish(self) -> WritableObject<'file> {
-        self.obj
+    /// Returns the name and uncompressed size of each section, in the order the sections were
+    /// created.
+    fn section_sizes(&self) -> Vec<(String, u64)> {
+        self.sizes.values().cloned().collect()
+    }
+
+    /// Return the DWARF package object file, compressing its sections if requested, and writing an
+    /// uncompressed `.note.gnu.build-id` section with a note for each of `build_ids` (if there are
+    /// any).
+    pub(crate) fn finish(self, build_ids: &[Vec<u8>]) -> Result<OutputObject<'file>> {
+        if !build_ids.is_empty() && self.format != OutputFormat::Elf {
+            return Err(Error::UnsupportedBuildIdNoteFormat);
+        }
+
+        let endian = self.endianness.as_runtime_endian();
+        let mut obj = self.compress()?;
+        if !build_ids.is_empty() {
+            debug!("writing build id notes");
+            let notes = build_id_notes(endian, build_ids)?;
+            let id =
+                obj.add_section(Vec::new(), b".note.gnu.build-id".to_vec(), SectionKind::Note)?;
+            let _ = obj.append_section_data(id, notes.slice(), NOTE_ALIGN)?;
+        }
+
+        Ok(obj)
+    }
+
+    /// Return the DWARF package object file, compressing its sections if requested.
+    fn compress(self) -> Result<OutputObject<'file>> {
+        let compression = match self.compression {
+            Some(compression) => compression,
+            None => return Ok(self.obj),
+        };
+
+        let compress_index = self.compress_index_sections;
+        let sections: Vec<_> = [
+            (self.debug_cu_index, compress_index),
+            (self.debug_tu_index, compress_index),
+            (self.debug_info, true),
+            (self.debug_abbrev, true),
+            (self.debug_str, true),
+            (self.debug_types, true),
+            (self.debug_line, true),
+            (self.debug_loc, true),
+            (self.debug_loclists, true),
+            (self.debug_rnglists, true),
+            (self.debug_str_offsets, true),
+            (self.debug_macinfo, true),
+            (self.debug_macro, true),
+        ]
+        .into_iter()
+        .filter_map(|(id, compress)| id.map(|id| (id, self.alignments[&id], compress)))
+        .collect();
+        self.obj.compress_sections(&sections, compression, self.endianness, self.is_64)
     }
 }
 
@@ -398,6 +1441,18 @@ pub(crate) struct InProgressDwarfPackage<'file> {
     /// specification). Also used to check that all dwarf objects referenced by executables
     /// have been found.
     contained_units: HashSet<DwarfObject>,
+
+    /// Statistics about the inputs and the DWARF package, completed when the package is finished.
+    statistics: PackageStatistics,
+
+    /// Entries of the type units that have been added to the output package, and where each type
+    /// unit was read from, if duplicate type units are being compared with them.
+    type_unit_trees: Option<HashMap<DebugTypeSignature, (InputLocation, TypeUnitTree)>>,
+
+    /// Contents of the compilation units that have been added to the output package, if duplicate
+    /// compilation units are only skipped if they are identical (see
+    /// `DuplicateUnitBehaviour::KeepIfIdentical`).
+    compilation_unit_data: HashMap<DwoId, Vec<u8>>,
 }
 
 impl<'file> InProgressDwarfPackage<'file> {
@@ -405,18 +1460,33 @@ impl<'file> InProgressDwarfPackage<'file> {
     /// files.
     #[tracing::instrument(level = "trace")]
     pub(crate) fn new(
+        format: OutputFormat,
         architecture: object::Architecture,
         endianness: object::Endianness,
-    ) -> InProgressDwarfPackage<'file> {
+        streaming_dir: Option<&Path>,
+        compression: Option<OutputCompression>,
+        compress_index_sections: bool,
+        compare_type_units: bool,
+    ) -> Result<InProgressDwarfPackage<'file>> {
         let endian = endianness.as_runtime_endian();
-        Self {
+        Ok(Self {
             endian,
-            obj: DwarfPackageObject::new(architecture, endianness),
+            obj: DwarfPackageObject::new(
+                format,
+                architecture,
+                endianness,
+                streaming_dir,
+                compression,
+                compress_index_sections,
+            )?,
             string_table: PackageStringTable::new(endian),
             cu_index_entries: Default::default(),
             tu_index_entries: Default::default(),
             contained_units: Default::default(),
-        }
+            statistics: Default::default(),
+            type_unit_trees: compare_type_units.then(HashMap::new),
+            compilation_unit_data: Default::default(),
+        })
     }
 
     /// Returns the units contained within the DWARF package.
@@ -424,27 +1494,107 @@ impl<'file> InProgressDwarfPackage<'file> {
         &self.contained_units
     }
 
-    /// Process an input DWARF object. Copies relevant sections, compilation/type units and strings
-    /// from DWARF object into output object.
-    #[tracing::instrument(level = "trace", skip(sess, input,))]
-    pub(crate) fn add_input_object<'input, 'session: 'input>(
+    /// Returns the size of the sections of the DWARF package so far, excluding the index sections
+    /// which are written when the package is finished.
+    pub(crate) fn size(&self) -> u64 {
+        self.obj.sizes.values().map(|(_, size)| size).sum::<u64>() + self.string_table.size()
+    }
+
+    /// Record that an input object was added to the DWARF package, whether or not it contained a
+    /// DWARF object.
+    pub(crate) fn count_input_object(&mut self) {
+        self.statistics.input_objects += 1;
+    }
+
+    /// Append the data of `section` from `input` (which has alignment `input_align` in the input)
+    /// to the corresponding section of the output object, or only the data in `range` if
+    /// provided. Strings referenced by `.debug_str_offsets.dwo` are added to the string table.
+    fn append_section<'a>(
         &mut self,
-        sess: &'session impl Session<RelocationMap>,
-        input: &object::File<'input>,
-        encoding: Encoding,
+        input: &PreparedContents<'_>,
+        section: &'a PreparedSection<'_>,
+        input_align: u64,
+        range: Option<Range<usize>>,
+    ) -> Result<Option<Contribution>> {
+        let encoding = input.encoding;
+        let id = section.id();
+        let section_name = id.dwo_name().expect("section id w/out known value");
+        let align = contribution_align(id, encoding.format, input_align);
+        let data = |data: &'a [u8]| -> Result<&'a [u8]> {
+            match &range {
+                Some(range) => data
+                    .get(range.clone())
+                    .ok_or_else(|| Error::from(gimli::Error::OffsetOutOfBounds))
+                    .map_err(|e| e.in_section(section_name, Some(range.start as u64))),
+                None => Ok(data),
+            }
+        };
+        match section {
+            PreparedSection::DebugAbbrev(d) => self.obj.append_to_debug_abbrev(data(d)?, align),
+            PreparedSection::DebugLine(d) => self.obj.append_to_debug_line(data(d)?, align),
+            PreparedSection::DebugLoc(d) => self.obj.append_to_debug_loc(data(d)?, align),
+            PreparedSection::DebugLocLists(d) => self.obj.append_to_debug_loclists(data(d)?, align),
+            PreparedSection::DebugMacinfo(d) => self.obj.append_to_debug_macinfo(data(d)?, align),
+            PreparedSection::DebugMacro(d) => self.obj.append_to_debug_macro(data(d)?, align),
+            PreparedSection::DebugRngLists(d) => self.obj.append_to_debug_rnglists(data(d)?, align),
+            PreparedSection::DebugStrOffsets { size, strings } => {
+                let debug_str = input
+                    .debug_str
+                    .as_ref()
+                    .expect("`.debug_str_offsets.dwo` w/out `.debug_str.dwo`");
+                let (strings, size) = match range {
+                    Some(range) => {
+                        let offset = range.start as u64;
+                        let size = range.len() as u64;
+                        let strings = str_offsets_contribution_strings(strings, range, encoding)
+                            .ok_or_else(|| Error::from(gimli::Error::OffsetOutOfBounds))
+                            .map_err(|e| e.in_section(section_name, Some(offset)))?;
+                        (strings, size)
+                    }
+                    None => (strings.as_slice(), *size),
+                };
+                let data = self
+                    .string_table
+                    .remap_str_offsets_section(debug_str, strings, size, self.endian, encoding)
+                    .map_err(|e| e.in_section(section_name, None))?;
+                self.obj.append_to_debug_str_offsets(data.slice(), align)
+            }
+        }
+    }
+
+    /// Process a prepared input DWARF object from `location`. Copies relevant sections,
+    /// compilation/type units and strings from DWARF object into output object.
+    ///
+    /// Units of the input in `skipped_units` aren't copied into the output object, such as
+    /// compilation units of a DWARF package being updated which were replaced or removed, or units
+    /// which weren't selected by a `UnitFilter`. Compilation units which are already in the output
+    /// package are handled according to `duplicate_behaviour`.
+    #[tracing::instrument(level = "trace", skip(input, skipped_units, warn))]
+    pub(crate) fn add_input_object(
+        &mut self,
+        input: &PreparedContents<'_>,
+        location: &InputLocation,
+        skipped_units: &HashSet<DwarfObject>,
+        duplicate_behaviour: DuplicateUnitBehaviour,
+        warn: &mut dyn FnMut(Warning),
     ) -> Result<()> {
+        // Nothing is copied from inputs whose units are all skipped.
+        if !skipped_units.is_empty() && input.units().all(|id| skipped_units.contains(&id)) {
+            return Ok(());
+        }
+
+        let encoding = input.encoding;
+
         // Load index sections (if they exist).
-        let cu_index = maybe_load_index_section::<_, gimli::DebugCuIndex<_>, _, _>(
-            sess,
+        let cu_index = maybe_load_index_section::<_, gimli::DebugCuIndex<_>, _>(
             encoding,
-            self.endian,
-            input,
+            input.endian,
+            input.debug_cu_index.as_deref(),
         )?;
-        let tu_index = maybe_load_index_section::<_, gimli::DebugTuIndex<_>, _, _>(
-            sess,
+        let tu_index = maybe_load_index_section::<_, gimli::DebugTuIndex<_>, _>(
             encoding,
-            self.endian,
-            input,
+            input.endian,
+            input.debug_tu_index.as_deref(),
         )?;
 
         let mut debug_abbrev = None;
@@ -456,90 +1606,70 @@ impl<'file> InProgressDwarfPackage<'file> {
         let mut debug_rnglists = None;
         let mut debug_str_offsets = None;
 
+        // Contributions from repeated sections in an input are combined, including any padding
+        // between them.
         macro_rules! update {
             ($target:ident += $source:expr) => {
                 if let Some(other) = $source {
                     let contribution = $target.get_or_insert(Contribution { size: 0, ..other });
-                    contribution.size += other.size;
+                    contribution.size = other.offset.0 + other.size - contribution.offset.0;
                 }
                 debug!(?$target);
             };
         }
 
-        // Iterate over sections rather than using `section_by_name` because sections can be
-        // repeated.
-        for section in input.sections() {
-            match section.name().map_err(Error::NonUtf8SectionName)? {
-                ".debug_abbrev.dwo" | ".zdebug_abbrev.dwo" => {
-                    let data = section.compressed_data()?.decompress()?;
-                    update!(debug_abbrev += self.obj.append_to_debug_abbrev(&data));
-                }
-                ".debug_line.dwo" | ".zdebug_line.dwo" => {
-                    let data = section.compressed_data()?.decompress()?;
-                    update!(debug_line += self.obj.append_to_debug_line(&data));
-                }
-                ".debug_loc.dwo" | ".zdebug_loc.dwo" => {
-                    let data = section.compressed_data()?.decompress()?;
-                    update!(debug_loc += self.obj.append_to_debug_loc(&data));
-                }
-                ".debug_loclists.dwo" | ".zdebug_loclists.dwo" => {
-                    let data = section.compressed_data()?.decompress()?;
-                    update!(debug_loclists += self.obj.append_to_debug_loclists(&data));
-                }
-                ".debug_macinfo.dwo" | ".zdebug_macinfo.dwo" => {
-                    let data = section.compressed_data()?.decompress()?;
-                    update!(debug_macinfo += self.obj.append_to_debug_macinfo(&data));
+        // When some units of a DWARF package are skipped (e.g. because they weren't selected by a
+        // `UnitFilter` or are in another shard), only the contributions of the units which are
+        // added are copied, rather than whole sections. Packages with repeated sections are always
+        // copied whole.
+        let partial_sections =
+            if cu_index.is_some() && input.units().any(|id| skipped_units.contains(&id)) {
+                let mut sections = HashMap::new();
+                let mut repeated = false;
+                for (section, input_align) in &input.sections {
+                    repeated |= sections.insert(section.id(), (section, *input_align)).is_some();
                 }
-                ".debug_macro.dwo" | ".zdebug_macro.dwo" => {
-                    let data = section.compressed_data()?.decompress()?;
-                    update!(debug_macro += self.obj.append_to_debug_macro(&data));
-                }
-                ".debug_rnglists.dwo" | ".zdebug_rnglists.dwo" => {
-                    let data = section.compressed_data()?.decompress()?;
-                    update!(debug_rnglists += self.obj.append_to_debug_rnglists(&data));
+                if repeated {
+                    None
+                } else {
+                    Some(sections)
                 }
-                ".debug_str_offsets.dwo" | ".zdebug_str_offsets.dwo" => {
-                    let debug_str_offsets_section = {
-                        let data = section.compressed_data()?.decompress()?;
-                        let data_ref = sess.alloc_owned_cow(data);
-                        gimli::DebugStrOffsets::from(gimli::EndianSlice::new(data_ref, self.endian))
-                    };
-
-                    let debug_str_section =
-                        if let Some(section) = input.section_by_name(".debug_str.dwo") {
-                            let data = section.compressed_data()?.decompress()?;
-                            let data_ref = sess.alloc_owned_cow(data);
-                            gimli::DebugStr::new(data_ref, self.endian)
-                        } else {
-                            return Err(Error::MissingRequiredSection(".debug_str.dwo"));
-                        };
+            } else {
+                None
+            };
 
-                    let data = self.string_table.remap_str_offsets_section(
-                        debug_str_section,
-                        debug_str_offsets_section,
-                        section.size(),
-                        self.endian,
-                        encoding,
-                    )?;
-                    update!(
-                        debug_str_offsets += self.obj.append_to_debug_str_offsets(data.slice())
-                    );
+        if partial_sections.is_none() {
+            for (section, input_align) in &input.sections {
+                let contribution = self.append_section(input, section, *input_align, None)?;
+                match section {
+                    PreparedSection::DebugAbbrev(_) => {
+                        update!(debug_abbrev += contribution);
+                    }
+                    PreparedSection::DebugLine(_) => {
+                        update!(debug_line += contribution);
+                    }
+                    PreparedSection::DebugLoc(_) => {
+                        update!(debug_loc += contribution);
+                    }
+                    PreparedSection::DebugLocLists(_) => {
+                        update!(debug_loclists += contribution);
+                    }
+                    PreparedSection::DebugMacinfo(_) => {
+                        update!(debug_macinfo += contribution);
+                    }
+                    PreparedSection::DebugMacro(_) => {
+                        update!(debug_macro += contribution);
+                    }
+                    PreparedSection::DebugRngLists(_) => {
+                        update!(debug_rnglists += contribution);
+                    }
+                    PreparedSection::DebugStrOffsets { .. } => {
+                        update!(debug_str_offsets += contribution);
+                    }
                 }
-                _ => (),
             }
         }
 
-        // `.debug_abbrev.dwo`'s contribution will already have been processed, but getting the
-        // `DwoId` of a GNU Extension compilation unit requires access to it.
-        let debug_abbrev_section = if let Some(section) = input.section_by_name(".debug_abbrev.dwo")
-        {
-            let data = section.compressed_data()?.decompress()?;
-            let data_ref = sess.alloc_owned_cow(data);
-            gimli::DebugAbbrev::new(data_ref, self.endian)
-        } else {
-            return Err(Error::MissingRequiredSection(".debug_abbrev.dwo"));
-        };
-
         // Create offset adjustor functions, see comment on `create_contribution_adjustor` for
         // explanation.
         let mut abbrev_adjustor = create_contribution_adjustor(
@@ -583,104 +1713,200 @@ impl<'file> InProgressDwarfPackage<'file> {
             gimli::SectionId::DebugMacro,
         );
 
-        let mut seen_debug_info = false;
-        let mut seen_debug_types = false;
+        // Strings referenced by the input, only needed when comparing type units.
+        let strings = match self.type_unit_trees {
+            Some(_) => input.strings(),
+            None => Vec::new(),
+        };
+        let read_type_unit_tree = |section: &PreparedUnitSection<'_>, unit: &PreparedUnit| {
+            let sections =
+                input.unit_sections(&strings, cu_index.as_ref(), tu_index.as_ref(), unit.id)?;
+            TypeUnitTree::read(
+                &section.data[unit.range.clone()],
+                unit.range.start as u64,
+                section.is_debug_types,
+                &sections,
+            )
+        };
 
-        for section in input.sections() {
-            let data = section.compressed_data()?.decompress()?;
-            let (is_debug_types, mut iter) = match section.name().map_err(Error::NonUtf8SectionName)? {
-                ".debug_info.dwo" | ".zdebug_info.dwo"
-                    // Report an error if a input DWARF package has multiple `.debug_info`
-                    // sections.
-                    if seen_debug_info && cu_index.is_some() =>
-                {
-                    return Err(Error::MultipleDebugInfoSection);
-                }
-                ".debug_info.dwo" | ".zdebug_info.dwo" => {
-                    seen_debug_info = true;
-                    (
-                        false,
-                        UnitHeaderIterator::DebugInfo(
-                            gimli::DebugInfo::new(&data, self.endian).units(),
-                        ),
-                    )
-                }
-                ".debug_types.dwo" | ".zdebug_types.dwo"
-                    // Report an error if a input DWARF package has multiple `.debug_types`
-                    // sections.
-                    if seen_debug_types && tu_index.is_some() =>
-                {
-                    return Err(Error::MultipleDebugTypesSection);
-                }
-                ".debug_types.dwo" | ".zdebug_types.dwo" => {
-                    seen_debug_types = true;
-                    (
-                        true,
-                        UnitHeaderIterator::DebugTypes(
-                            gimli::DebugTypes::new(&data, self.endian).units(),
-                        ),
-                    )
+        // Returns the contribution of a unit to a section: the input's contribution to the section
+        // adjusted by `adjustor`, or the contribution of the unit copied from the input if only
+        // some units of the input are added (which is copied once if shared by multiple units).
+        let mut copied = HashMap::new();
+        let mut contribution = |this: &mut Self,
+                                id: DwarfObject,
+                                whole: Option<Contribution>,
+                                adjustor: &mut ContributionAdjustor<'_>,
+                                section_id: gimli::SectionId|
+         -> Result<Option<Contribution>> {
+            let sections = match &partial_sections {
+                Some(sections) => sections,
+                None => return adjustor(id, whole),
+            };
+            let (section, input_align) = match sections.get(&section_id) {
+                Some(section) => *section,
+                None => return Ok(None),
+            };
+            let (index, index_name) = match id {
+                DwarfObject::Compilation(_) => (cu_index.as_ref(), ".debug_cu_index"),
+                DwarfObject::Type(_) => (tu_index.as_ref(), ".debug_tu_index"),
+            };
+            let index = match index {
+                Some(index) => index,
+                None => return Ok(None),
+            };
+
+            let idx = id.index();
+            let index_section = index
+                .find(idx)
+                .ok_or(Error::UnitNotInIndex(idx))
+                .and_then(|row_id| {
+                    Ok(index
+                        .sections(row_id)
+                        .map_err(|e| Error::RowNotInIndex(e, row_id))?
+                        .find(|index_section| index_section.section == section_id))
+                })
+                .map_err(|e| e.in_section(index_name, None))?;
+            let range = match index_section {
+                Some(index_section) => {
+                    let offset = index_section.offset as usize;
+                    offset..offset + index_section.size as usize
                 }
-                _ => continue,
+                None => return Ok(None),
             };
 
-            while let Some(header) = iter.next().map_err(Error::ParseUnitHeader)? {
-                let id = match dwo_identifier_of_unit(&debug_abbrev_section, &header)? {
-                    // Report an error if the unit doesn't have a `DwoId` or `DebugTypeSignature`.
-                    None => {
-                        return Err(Error::NotSplitUnit);
+            if let Some(contribution) = copied.get(&(section_id, range.start)) {
+                return Ok(Some(*contribution));
+            }
+            let start = range.start;
+            let contribution = this.append_section(input, section, input_align, Some(range))?;
+            if let Some(contribution) = contribution {
+                copied.insert((section_id, start), contribution);
+            }
+            Ok(contribution)
+        };
+
+        for section in &input.unit_sections {
+            let section_name =
+                if section.is_debug_types { ".debug_types.dwo" } else { ".debug_info.dwo" };
+            let unit_location = |unit: &PreparedUnit| InputLocation {
+                section: Some(section_name.to_string()),
+                offset: Some(unit.range.start as u64),
+                ..location.clone()
+            };
+            for unit in &section.units {
+                let id = match unit.id {
+                    id if skipped_units.contains(&id) => {
+                        debug!(?id, "skipping unit");
+                        continue;
                     }
-                    // Report an error when a duplicate compilation unit is found.
-                    Some(id @ DwarfObject::Compilation(dwo_id))
-                        if self.contained_units.contains(&id) =>
-                    {
-                        return Err(Error::DuplicateUnit(dwo_id.0));
+                    // Report an error, skip or replace the earlier unit when a duplicate compilation
+                    // unit is found, depending on `duplicate_behaviour`.
+                    id @ DwarfObject::Compilation(dwo_id) if self.contained_units.contains(&id) => {
+                        let data = &section.data[unit.range.clone()];
+                        match duplicate_behaviour {
+                            DuplicateUnitBehaviour::Error => {
+                                return Err(Error::DuplicateUnit(dwo_id.0)
+                                    .in_section(section_name, Some(unit.range.start as u64)));
+                            }
+                            DuplicateUnitBehaviour::KeepIfIdentical
+                                if self.compilation_unit_data.get(&dwo_id).map(Vec::as_slice)
+                                    != Some(data) =>
+                            {
+                                return Err(Error::MismatchedDuplicateUnit(dwo_id.0)
+                                    .in_section(section_name, Some(unit.range.start as u64)));
+                            }
+                            DuplicateUnitBehaviour::KeepFirst
+                            | DuplicateUnitBehaviour::KeepIfIdentical => {
+                                debug!(?dwo_id, "skipping duplicate compilation unit");
+                                warn(Warning::DuplicateCompilationUnit(
+                                    unit_location(unit),
+                                    dwo_id,
+                                ));
+                                continue;
+                            }
+                            DuplicateUnitBehaviour::KeepLast => {
+                                debug!(?dwo_id, "replacing duplicate compilation unit");
+                                warn(Warning::DuplicateCompilationUnit(
+                                    unit_location(unit),
+                                    dwo_id,
+                                ));
+                                self.cu_index_entries.retain(|entry| entry.id != id);
+                                id
+                            }
+                        }
                     }
                     // Skip duplicate type units, these happen during proper operation of `thorin`.
-                    Some(id @ DwarfObject::Type(type_sig))
-                        if self.contained_units.contains(&id) =>
-                    {
+                    id @ DwarfObject::Type(type_sig) if self.contained_units.contains(&id) => {
                         debug!(?type_sig, "skipping duplicate type unit, already seen");
+                        let duplicate_location = unit_location(unit);
+                        if let Some((kept_location, kept)) =
+                            self.type_unit_trees.as_ref().and_then(|trees| trees.get(&type_sig))
+                        {
+                            let tree = read_type_unit_tree(section, unit).map_err(|e| {
+                                e.in_section(section_name, Some(unit.range.start as u64))
+                            })?;
+                            if let Some((kept_offset, offset)) = kept.first_difference(&tree) {
+                                warn(Warning::MismatchedTypeUnit(
+                                    type_sig,
+                                    InputLocation {
+                                        offset: Some(kept_offset),
+                                        ..kept_location.clone()
+                                    },
+                                    InputLocation {
+                                        offset: Some(offset),
+                                        ..duplicate_location.clone()
+                                    },
+                                ));
+                            }
+                        }
+                        warn(Warning::DuplicateTypeUnit(duplicate_location, type_sig));
+                        self.statistics.duplicate_type_units += 1;
+                        self.statistics.duplicate_type_unit_bytes += unit.range.len() as u64;
                         continue;
                     }
-                    Some(id) => id,
+                    id => id,
                 };
 
-                let size: u64 = header
-                    .length_including_self()
-                    .try_into()
-                    .expect("unit header length larger than u64");
-                let offset = match header.offset() {
-                    UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
-                    UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
-                };
-
-                let data = section
-                    .compressed_data_range(
-                        sess,
-                        offset.try_into().expect("offset larger than u64"),
-                        size,
-                    )
-                    .map_err(Error::DecompressData)?
-                    .ok_or(Error::EmptyUnit(id.index()))?;
+                if let (DwarfObject::Compilation(dwo_id), DuplicateUnitBehaviour::KeepIfIdentical) =
+                    (id, duplicate_behaviour)
+                {
+                    self.compilation_unit_data
+                        .insert(dwo_id, section.data[unit.range.clone()].to_vec());
+                }
+                if let (DwarfObject::Type(type_sig), Some(trees)) = (id, &mut self.type_unit_trees)
+                {
+                    let tree = read_type_unit_tree(section, unit)
+                        .map_err(|e| e.in_section(section_name, Some(unit.range.start as u64)))?;
+                    trees.insert(type_sig, (unit_location(unit), tree));
+                }
 
+                let data = &section.data[unit.range.clone()];
                 let (debug_info, debug_types) = match id {
-                    DwarfObject::Type(_) if is_debug_types => {
-                        (None, self.obj.append_to_debug_types(data))
+                    DwarfObject::Type(_) if section.is_debug_types => {
+                        (None, self.obj.append_to_debug_types(data, 1)?)
                     }
                     DwarfObject::Compilation(_) | DwarfObject::Type(_) => {
-                        (self.obj.append_to_debug_info(data), None)
+                        (self.obj.append_to_debug_info(data, 1)?, None)
                     }
                 };
 
-                let debug_abbrev = abbrev_adjustor(id, debug_abbrev)?;
-                let debug_line = line_adjustor(id, debug_line)?;
-                let debug_loc = loc_adjustor(id, debug_loc)?;
-                let debug_loclists = loclists_adjustor(id, debug_loclists)?;
-                let debug_rnglists = rnglists_adjustor(id, debug_rnglists)?;
-                let debug_str_offsets = str_offsets_adjustor(id, debug_str_offsets)?;
-                let debug_macinfo = macinfo_adjustor(id, debug_macinfo)?;
-                let debug_macro = macro_adjustor(id, debug_macro)?;
+                macro_rules! contribution {
+                    ($whole:ident, $adjustor:ident, $section:ident) => {
+                        contribution(self, id, $whole, &mut $adjustor, gimli::SectionId::$section)?
+                    };
+                }
+                let debug_abbrev = contribution!(debug_abbrev, abbrev_adjustor, DebugAbbrev);
+                let debug_line = contribution!(debug_line, line_adjustor, DebugLine);
+                let debug_loc = contribution!(debug_loc, loc_adjustor, DebugLoc);
+                let debug_loclists =
+                    contribution!(debug_loclists, loclists_adjustor, DebugLocLists);
+                let debug_rnglists =
+                    contribution!(debug_rnglists, rnglists_adjustor, DebugRngLists);
+                let debug_str_offsets =
+                    contribution!(debug_str_offsets, str_offsets_adjustor, DebugStrOffsets);
+                let debug_macinfo = contribution!(debug_macinfo, macinfo_adjustor, DebugMacinfo);
+                let debug_macro = contribution!(debug_macro, macro_adjustor, DebugMacro);
 
                 let entry = IndexEntry {
                     encoding,
@@ -706,30 +1932,36 @@ impl<'file> InProgressDwarfPackage<'file> {
             }
         }
 
-        if !seen_debug_info {
-            // Report an error if no `.debug_info` section was found.
-            return Err(Error::MissingRequiredSection(".debug_info.dwo"));
-        }
-
         Ok(())
     }
 
-    /// Return the DWARF package object being created, writing any final sections.
-    pub(crate) fn finish(self) -> Result<WritableObject<'file>> {
-        let Self { mut obj, string_table, cu_index_entries, tu_index_entries, .. } = self;
+    /// Return the DWARF package object being created, writing any final sections (including a
+    /// `.note.gnu.build-id` section with a note for each of `build_ids`, if there are any), and
+    /// statistics about the DWARF package.
+    pub(crate) fn finish(
+        self,
+        build_ids: &[Vec<u8>],
+    ) -> Result<(OutputObject<'file>, PackageStatistics)> {
+        let Self {
+            mut obj, string_table, cu_index_entries, tu_index_entries, mut statistics, ..
+        } = self;
+        statistics.compilation_units = cu_index_entries.len();
+        statistics.type_units = tu_index_entries.len();
+        statistics.duplicate_string_bytes = string_table.duplicate_bytes();
 
         // Write `.debug_str` to the object.
-        let _ = obj.append_to_debug_str(string_table.finish().slice());
+        let _ = obj.append_to_debug_str(string_table.finish().slice(), 1)?;
 
         // Write `.debug_{cu,tu}_index` sections to the object.
         debug!("writing cu index");
         let cu_index_data = write_index(self.endian, &cu_index_entries)?;
-        let _ = obj.append_to_debug_cu_index(cu_index_data.slice());
+        let _ = obj.append_to_debug_cu_index(cu_index_data.slice(), INDEX_SECTION_ALIGN)?;
         debug!("writing tu index");
         let tu_index_data = write_index(self.endian, &tu_index_entries)?;
-        let _ = obj.append_to_debug_tu_index(tu_index_data.slice());
+        let _ = obj.append_to_debug_tu_index(tu_index_data.slice(), INDEX_SECTION_ALIGN)?;
 
-        Ok(obj.finish())
+        statistics.section_sizes = obj.section_sizes();
+        Ok((obj.finish(build_ids)?, statistics))
     }
 }
 
@@ -0,0 +1,337 @@
+use std::{collections::HashSet, path::Path};
+
+use gimli::{EndianSlice, Reader, RunTimeEndian};
+use indexmap::IndexMap;
+use object::{Object, ObjectSection};
+use tracing::debug;
+
+use crate::{
+    error::{Error, Result},
+    ext::{DwoSectionIdExt, EndianityExt, IndexSectionExt},
+    index::Bucketable,
+    package::{dwo_identifier_of_unit, DebugTypeSignature, DwarfObject, DwoId},
+    relocate::RelocationMap,
+    Session,
+};
+
+/// Contribution of a unit to a section of a DWARF package.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct UnitContribution {
+    /// Section that the unit contributes to.
+    pub section: gimli::SectionId,
+    /// Offset of the contribution from the start of the section.
+    pub offset: u64,
+    /// Size of the contribution.
+    pub size: u64,
+}
+
+impl UnitContribution {
+    /// Returns the name of the section that the unit contributes to (in ELF DWARF packages).
+    pub fn section_name(&self) -> &'static str {
+        self.section.dwo_section_name().unwrap_or("<unknown>")
+    }
+}
+
+/// Unit in the index of a DWARF package.
+#[derive(Clone, Debug)]
+pub struct PackageUnit {
+    id: DwarfObject,
+    contributions: Vec<UnitContribution>,
+}
+
+impl PackageUnit {
+    /// Returns the `DwoId` or `DebugTypeSignature` of the unit.
+    pub fn id(&self) -> DwarfObject {
+        self.id
+    }
+
+    /// Returns the contributions of the unit to each section of the DWARF package, in the order of
+    /// the columns of the index.
+    pub fn contributions(&self) -> &[UnitContribution] {
+        &self.contributions
+    }
+
+    /// Returns the contribution of the unit to `section`, if it has one.
+    pub fn contribution(&self, section: gimli::SectionId) -> Option<&UnitContribution> {
+        self.contributions.iter().find(|contribution| contribution.section == section)
+    }
+}
+
+/// Read-only view of an existing DWARF package, providing access to the units in its
+/// `.debug_cu_index` and `.debug_tu_index` sections and to their contributions.
+#[derive(Debug)]
+pub struct DwarfPackageReader<'input> {
+    /// Endianness of the DWARF package.
+    endian: RunTimeEndian,
+    /// Version of the index sections of the DWARF package.
+    index_version: u16,
+    /// Contents of the DWARF sections of the DWARF package.
+    sections: IndexMap<gimli::SectionId, &'input [u8]>,
+    /// Compilation units followed by type units, in the order of the rows of the indexes.
+    units: IndexMap<DwarfObject, PackageUnit>,
+}
+
+impl<'input> DwarfPackageReader<'input> {
+    /// Read the DWARF package at `path`, which must be an elf or mach-o object, parsing its
+    /// index sections.
+    ///
+    /// Returns an `Error::DuplicateIndexEntry` if an index has more than one row for a unit.
+    #[tracing::instrument(level = "trace", skip(sess))]
+    pub fn new<'session: 'input, Sess>(sess: &'session Sess, path: &Path) -> Result<Self>
+    where
+        Sess: Session<RelocationMap>,
+    {
+        let data = sess.read_input(path).map_err(Error::ReadInput)?;
+        let obj = object::File::parse(data).map_err(Error::ParseObjectFile)?;
+        let endian = obj.endianness().as_runtime_endian();
+        let in_input = |e: Error| e.in_input(path);
+
+        let mut sections = IndexMap::new();
+        for section in obj.sections() {
+            if let Some(id) =
+                gimli::SectionId::from_dwo_section(&obj, &section).map_err(in_input)?
+            {
+                let data = section.compressed_data()?.decompress()?;
+                sections.insert(id, sess.alloc_owned_cow(data));
+            }
+        }
+
+        // DWARF packages which only contain type units don't have a `.debug_cu_index`, so a
+        // missing index is treated as empty (but one of the indexes must exist).
+        let cu_index = sections.get(&gimli::SectionId::DebugCuIndex).copied();
+        let tu_index = sections.get(&gimli::SectionId::DebugTuIndex).copied();
+        if cu_index.is_none() && tu_index.is_none() {
+            return Err(Error::MissingRequiredSection(".debug_cu_index"));
+        }
+
+        let mut index_version = 0;
+        let mut units = IndexMap::new();
+        if let Some(cu_index) = cu_index {
+            let (version, compilation_units) =
+                read_index::<gimli::DebugCuIndex<_>>(cu_index, endian, |id| {
+                    DwarfObject::Compilation(DwoId(id))
+                })
+                .map_err(in_input)?;
+            index_version = version;
+            units.extend(compilation_units.into_iter().map(|unit| (unit.id, unit)));
+        }
+        if let Some(tu_index) = tu_index {
+            let (version, type_units) =
+                read_index::<gimli::DebugTuIndex<_>>(tu_index, endian, |id| {
+                    DwarfObject::Type(DebugTypeSignature(id))
+                })
+                .map_err(in_input)?;
+            if cu_index.is_none() {
+                index_version = version;
+            }
+            units.extend(type_units.into_iter().map(|unit| (unit.id, unit)));
+        }
+
+        debug!(unit_count = units.len());
+        Ok(Self { endian, index_version, sections, units })
+    }
+
+    /// Returns the version of the index sections of the DWARF package (two for the GNU extension
+    /// format, five for the standardized format).
+    pub fn index_version(&self) -> u16 {
+        self.index_version
+    }
+
+    /// Returns the units in the DWARF package, compilation units followed by type units.
+    pub fn units(&self) -> impl Iterator<Item = &PackageUnit> {
+        self.units.values()
+    }
+
+    /// Returns the unit with the `DwoId` or `DebugTypeSignature` `id`, if the DWARF package
+    /// contains it.
+    pub fn unit(&self, id: DwarfObject) -> Option<&PackageUnit> {
+        self.units.get(&id)
+    }
+
+    /// Returns the contents of the DWARF package's section `section`, if it has one.
+    pub fn section_data(&self, section: gimli::SectionId) -> Option<&'input [u8]> {
+        self.sections.get(&section).copied()
+    }
+
+    /// Returns the data of a unit's contribution to a section of the DWARF package.
+    ///
+    /// Returns an `Error::InvalidContribution` if the contribution isn't within its section.
+    pub fn contribution_data(&self, contribution: &UnitContribution) -> Result<&'input [u8]> {
+        let section_name = contribution.section_name();
+        let invalid =
+            || Error::InvalidContribution(section_name, contribution.offset, contribution.size);
+
+        let data = self.section_data(contribution.section).ok_or_else(invalid)?;
+        let start = usize::try_from(contribution.offset).map_err(|_| invalid())?;
+        let size = usize::try_from(contribution.size).map_err(|_| invalid())?;
+        data.get(start..).and_then(|data| data.get(..size)).ok_or_else(invalid)
+    }
+
+    /// Returns a description of a unit in the DWARF package, read from its unit header and its
+    /// top-level debugging information entry.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn describe_unit(&self, unit: &PackageUnit) -> Result<UnitDescription> {
+        let contribution_data = |section| -> Result<_> {
+            let contribution = unit.contribution(section).ok_or(Error::SectionNotInRow)?;
+            Ok(EndianSlice::new(self.contribution_data(contribution)?, self.endian))
+        };
+
+        // Type units are in `.debug_types.dwo` with the GNU extension and in `.debug_info.dwo`
+        // with DWARF 5.
+        let header = if unit.contribution(gimli::SectionId::DebugTypes).is_some() {
+            gimli::DebugTypes::from(contribution_data(gimli::SectionId::DebugTypes)?).units().next()
+        } else {
+            gimli::DebugInfo::from(contribution_data(gimli::SectionId::DebugInfo)?).units().next()
+        }
+        .map_err(Error::ParseUnitHeader)?
+        .ok_or(Error::EmptyUnit(unit.id().index()))?;
+
+        let debug_abbrev =
+            gimli::DebugAbbrev::from(contribution_data(gimli::SectionId::DebugAbbrev)?);
+        let abbreviations =
+            header.abbreviations(&debug_abbrev).map_err(Error::ParseUnitAbbreviations)?;
+        let mut cursor = header.entries(&abbreviations);
+        cursor.next_dfs()?;
+        let root = cursor.current().ok_or(Error::NoDie)?;
+        let id = dwo_identifier_of_unit(&debug_abbrev, &header)?;
+
+        let encoding = header.encoding();
+        let attr_string = |name| -> Result<Option<String>> {
+            let value = match root.attr_value(name).map_err(Error::ParseUnitAttribute)? {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+
+            let string = match value {
+                gimli::AttributeValue::String(string) => string,
+                gimli::AttributeValue::DebugStrRef(offset) => {
+                    self.debug_str().get_str(offset).map_err(|e| Error::StrAtOffset(e, offset.0))?
+                }
+                gimli::AttributeValue::DebugStrOffsetsIndex(index) => {
+                    let debug_str_offsets = gimli::DebugStrOffsets::from(contribution_data(
+                        gimli::SectionId::DebugStrOffsets,
+                    )?);
+                    let base = gimli::DebugStrOffsetsBase::default_for_encoding_and_file(
+                        encoding,
+                        gimli::DwarfFileType::Dwo,
+                    );
+                    let offset = debug_str_offsets
+                        .get_str_offset(encoding.format, base, index)
+                        .map_err(|e| Error::OffsetAtIndex(e, index.0 as u64))?;
+                    self.debug_str().get_str(offset).map_err(|e| Error::StrAtOffset(e, offset.0))?
+                }
+                _ => return Err(Error::UnsupportedStringForm(name)),
+            };
+            Ok(Some(string.to_string_lossy().into_owned()))
+        };
+
+        Ok(UnitDescription {
+            unit_type: match header.type_() {
+                gimli::UnitType::Compilation => gimli::DW_UT_compile,
+                gimli::UnitType::Type { .. } => gimli::DW_UT_type,
+                gimli::UnitType::Partial => gimli::DW_UT_partial,
+                gimli::UnitType::Skeleton(_) => gimli::DW_UT_skeleton,
+                gimli::UnitType::SplitCompilation(_) => gimli::DW_UT_split_compile,
+                gimli::UnitType::SplitType { .. } => gimli::DW_UT_split_type,
+            },
+            version: encoding.version,
+            producer: attr_string(gimli::DW_AT_producer)?,
+            name: attr_string(gimli::DW_AT_name)?,
+            dwo_name: match attr_string(gimli::DW_AT_dwo_name)? {
+                Some(dwo_name) => Some(dwo_name),
+                None => attr_string(gimli::DW_AT_GNU_dwo_name)?,
+            },
+            comp_dir: attr_string(gimli::DW_AT_comp_dir)?,
+            id,
+        })
+    }
+
+    /// Returns the `.debug_str.dwo` section of the DWARF package.
+    fn debug_str(&self) -> gimli::DebugStr<EndianSlice<'input, RunTimeEndian>> {
+        let data = self.section_data(gimli::SectionId::DebugStr).unwrap_or_default();
+        gimli::DebugStr::new(data, self.endian)
+    }
+}
+
+/// Description of a unit in a DWARF package, see `DwarfPackageReader::describe_unit`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnitDescription {
+    /// Type of the unit (from its unit header with DWARF 5, or the section containing the unit
+    /// with the GNU extension).
+    pub unit_type: gimli::DwUt,
+    /// DWARF version of the unit.
+    pub version: u16,
+    /// `DW_AT_producer` of the unit, if it has one.
+    pub producer: Option<String>,
+    /// `DW_AT_name` of the unit, if it has one.
+    pub name: Option<String>,
+    /// `DW_AT_dwo_name` (or `DW_AT_GNU_dwo_name`) of the unit, if it has one.
+    pub dwo_name: Option<String>,
+    /// `DW_AT_comp_dir` of the unit, if it has one.
+    pub comp_dir: Option<String>,
+    /// `DwoId` or `DebugTypeSignature` read from the unit itself, which should match the
+    /// identifier of the unit in the index.
+    pub id: Option<DwarfObject>,
+}
+
+/// Returns the version of a `.debug_{cu,tu}_index` section and the units in it, ordered by row.
+///
+/// `gimli::UnitIndex` only supports looking up rows by identifier, so the hash table of the index
+/// is read directly (after `gimli` has validated the index) to find the identifier of each row.
+fn read_index<'input, Index>(
+    data: &'input [u8],
+    endian: RunTimeEndian,
+    unit_id: impl Fn(u64) -> DwarfObject,
+) -> Result<(u16, Vec<PackageUnit>)>
+where
+    Index: IndexSectionExt<'input, RunTimeEndian, EndianSlice<'input, RunTimeEndian>>,
+{
+    let index_name = Index::id().dwo_name().expect("index id w/out known value");
+    let parse_error = |e| Error::ParseIndex(e, index_name.to_string());
+
+    let index = Index::new(data, endian).index().map_err(parse_error)?;
+    let slot_count = index.slot_count();
+    if slot_count == 0 {
+        return Ok((index.version(), Vec::new()));
+    }
+
+    // Skip the header: version (and padding), section count, unit count and slot count.
+    let mut input = EndianSlice::new(data, endian);
+    input.skip(16).map_err(parse_error)?;
+    let mut hash_ids = input.split(slot_count as usize * 8).map_err(parse_error)?;
+    let mut hash_rows = input.split(slot_count as usize * 4).map_err(parse_error)?;
+
+    let mut rows = Vec::new();
+    let mut ids = HashSet::new();
+    for _ in 0..slot_count {
+        let id = hash_ids.read_u64().map_err(parse_error)?;
+        let row = hash_rows.read_u32().map_err(parse_error)?;
+        // Unused slots have a row of zero.
+        if row == 0 {
+            continue;
+        }
+        // Lookups by identifier only find one of the rows for a unit, so the others would be
+        // unreachable.
+        if !ids.insert(id) {
+            return Err(Error::DuplicateIndexEntry(id).in_section(index_name, None));
+        }
+        rows.push((id, row));
+    }
+    rows.sort_by_key(|&(_, row)| row);
+
+    let mut units = Vec::with_capacity(rows.len());
+    for (id, row) in rows {
+        let contributions = index
+            .sections(row)
+            .map_err(|e| Error::RowNotInIndex(e, row))?
+            .map(|section| UnitContribution {
+                section: section.section,
+                offset: section.offset.into(),
+                size: section.size.into(),
+            })
+            .collect();
+        units.push(PackageUnit { id: unit_id(id), contributions });
+    }
+
+    Ok((index.version(), units))
+}
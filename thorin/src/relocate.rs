@@ -4,12 +4,64 @@
 
 use std::{borrow::Cow, collections::HashMap};
 
-use gimli;
-use object::{Object, ObjectSection, ObjectSymbol, RelocationKind, RelocationTarget};
+use object::{Architecture, Object, ObjectSection, ObjectSymbol, RelocationKind, RelocationTarget};
 
 use crate::{Error, Result};
 
-pub(crate) type RelocationMap = HashMap<usize, object::Relocation>;
+/// Relocations of a section of an input object, by the offset of the value they apply to, which
+/// are applied as the section is read. Created by `thorin` and provided to
+/// `Session::alloc_relocation`.
+///
+/// Replaces the `HashMap<usize, object::Relocation>` which `Session` was implemented for in
+/// thorin-dwp 0.1, as add and subtract relocations of the same value are combined into a single
+/// relocation, which can't be represented as an `object::Relocation`.
+#[derive(Clone, Debug, Default)]
+pub struct RelocationMap(HashMap<usize, Relocation>);
+
+/// Relocation of a value in a section, which can combine multiple relocations of the same value
+/// (such as the pairs of add and subtract relocations used to compute differences of addresses
+/// on RISC-V).
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Relocation {
+    /// Whether `addend` is added to the value in the section, rather than replacing it (for
+    /// relocations with implicit addends, and add or subtract relocations).
+    implicit_addend: bool,
+    /// Relocated value, or the value added to the value in the section.
+    addend: u64,
+    /// Size of the relocated value in bits, or zero if the size isn't known.
+    size: u8,
+}
+
+impl Relocation {
+    /// Returns `value` (read from the section) after applying the relocation.
+    fn apply(&self, value: u64) -> u64 {
+        let value =
+            if self.implicit_addend { value.wrapping_add(self.addend) } else { self.addend };
+        match self.size {
+            size @ 1..=63 => value & ((1 << size) - 1),
+            _ => value,
+        }
+    }
+}
+
+/// How a relocation changes the value that it applies to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum RelocationOperation {
+    /// Replace the value with the relocated value (or add the relocated value to it, for
+    /// relocations with implicit addends).
+    Set,
+    /// Add the relocated value to the value.
+    Add,
+    /// Subtract the relocated value from the value.
+    Sub,
+    /// Leave the value unchanged, as it is never read as a relocated value (only addresses,
+    /// offsets and lengths are relocated, see `Relocate`).
+    Ignore,
+}
+
+/// RISC-V relocations which set and subtract from ULEB128 values (not defined by `object`).
+const R_RISCV_SET_ULEB128: u32 = 60;
+const R_RISCV_SUB_ULEB128: u32 = 61;
 
 #[derive(Debug, Clone)]
 pub(crate) struct Relocate<'a, R: gimli::Reader<Offset = usize>> {
@@ -20,18 +72,10 @@ pub(crate) struct Relocate<'a, R: gimli::Reader<Offset = usize>> {
 
 impl<'a, R: gimli::Reader<Offset = usize>> Relocate<'a, R> {
     fn relocate(&self, offset: usize, value: u64) -> u64 {
-        if let Some(relocation) = self.relocations.get(&offset) {
-            if matches!(relocation.kind(), RelocationKind::Absolute) {
-                if relocation.has_implicit_addend() {
-                    // Use the explicit addend too, because it may have the symbol value.
-                    return value.wrapping_add(relocation.addend() as u64);
-                } else {
-                    return relocation.addend() as u64;
-                }
-            }
-        };
-
-        value
+        match self.relocations.0.get(&offset) {
+            Some(relocation) => relocation.apply(value),
+            None => value,
+        }
     }
 }
 
@@ -45,6 +89,18 @@ impl<'a, R: gimli::Reader<Offset = usize>> gimli::Reader for Relocate<'a, R> {
         Ok(self.relocate(offset, value))
     }
 
+    fn read_initial_length(&mut self) -> gimli::Result<(usize, gimli::Format)> {
+        let offset = self.reader.offset_from(&self.section);
+        let (value, format) = self.reader.read_initial_length()?;
+        // 64-bit lengths are preceded by a 32-bit escape value, which isn't relocated.
+        let offset = match format {
+            gimli::Format::Dwarf32 => offset,
+            gimli::Format::Dwarf64 => offset + 4,
+        };
+        let value = <usize as gimli::ReaderOffset>::from_u64(self.relocate(offset, value as u64))?;
+        Ok((value, format))
+    }
+
     fn read_length(&mut self, format: gimli::Format) -> gimli::Result<usize> {
         let offset = self.reader.offset_from(&self.section);
         let value = self.reader.read_length(format)?;
@@ -139,48 +195,165 @@ impl<'a, R: gimli::Reader<Offset = usize>> gimli::Reader for Relocate<'a, R> {
     }
 }
 
+/// Returns the operation and size (in bits) of `relocation` in `file`, or `None` if relocations of
+/// its kind aren't supported.
+fn relocation_operation(
+    file: &object::File<'_>,
+    relocation: &object::Relocation,
+) -> Option<(RelocationOperation, u8)> {
+    use object::elf;
+
+    match (file.architecture(), relocation.kind()) {
+        (
+            _,
+            RelocationKind::Absolute | RelocationKind::Relative | RelocationKind::SectionOffset,
+        ) => Some((RelocationOperation::Set, relocation.size())),
+        (Architecture::Riscv32 | Architecture::Riscv64, RelocationKind::Elf(r_type)) => {
+            match r_type {
+                elf::R_RISCV_ADD8 => Some((RelocationOperation::Add, 8)),
+                elf::R_RISCV_ADD16 => Some((RelocationOperation::Add, 16)),
+                elf::R_RISCV_ADD32 => Some((RelocationOperation::Add, 32)),
+                elf::R_RISCV_ADD64 => Some((RelocationOperation::Add, 64)),
+                elf::R_RISCV_SUB8 => Some((RelocationOperation::Sub, 8)),
+                elf::R_RISCV_SUB16 => Some((RelocationOperation::Sub, 16)),
+                elf::R_RISCV_SUB32 => Some((RelocationOperation::Sub, 32)),
+                elf::R_RISCV_SUB64 => Some((RelocationOperation::Sub, 64)),
+                elf::R_RISCV_SET8 => Some((RelocationOperation::Set, 8)),
+                elf::R_RISCV_SET16 => Some((RelocationOperation::Set, 16)),
+                elf::R_RISCV_SET32 => Some((RelocationOperation::Set, 32)),
+                // Six-bit values (e.g. the deltas of `DW_CFA_advance_loc` in `.debug_frame`) and
+                // ULEB128 values (e.g. in `.debug_rnglists` and `.debug_loclists`) aren't read.
+                elf::R_RISCV_SET6
+                | elf::R_RISCV_SUB6
+                | R_RISCV_SET_ULEB128
+                | R_RISCV_SUB_ULEB128 => Some((RelocationOperation::Ignore, 0)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Why the relocated value of a relocation couldn't be computed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum InvalidRelocation {
+    /// Symbol or section targeted by the relocation doesn't exist.
+    InvalidTarget,
+    /// PC-relative relocation targets a different section than the section it applies to, so
+    /// its value depends on where the linker places the sections.
+    CrossSectionRelative,
+}
+
+/// Returns the relocated value of `relocation` at `offset` in `section` of `file` (excluding any
+/// implicit addend).
+fn relocated_value(
+    file: &object::File<'_>,
+    section: &object::Section<'_, '_>,
+    offset: usize,
+    relocation: &object::Relocation,
+) -> std::result::Result<u64, InvalidRelocation> {
+    // Address of the target of the relocation, and the index and address of the section
+    // containing it (if any).
+    let (target, target_section) = match relocation.target() {
+        RelocationTarget::Symbol(symbol_idx) => {
+            let symbol =
+                file.symbol_by_index(symbol_idx).map_err(|_| InvalidRelocation::InvalidTarget)?;
+            let target_section = match symbol.section_index() {
+                Some(index) => {
+                    let target_section = file
+                        .section_by_index(index)
+                        .map_err(|_| InvalidRelocation::InvalidTarget)?;
+                    Some((index, target_section.address()))
+                }
+                None => None,
+            };
+            (symbol.address(), target_section)
+        }
+        RelocationTarget::Section(index) => {
+            let target_section =
+                file.section_by_index(index).map_err(|_| InvalidRelocation::InvalidTarget)?;
+            (target_section.address(), Some((index, target_section.address())))
+        }
+        _ => (0, None),
+    };
+
+    let value = target.wrapping_add(relocation.addend() as u64);
+    Ok(match relocation.kind() {
+        // S + A - P, only when the target is in the same section, as addresses of sections in
+        // relocatable objects aren't known until they are linked.
+        RelocationKind::Relative => match target_section {
+            Some((index, _)) if index == section.index() => {
+                value.wrapping_sub(section.address().wrapping_add(offset as u64))
+            }
+            _ => return Err(InvalidRelocation::CrossSectionRelative),
+        },
+        // S + A - Section
+        RelocationKind::SectionOffset => {
+            value.wrapping_sub(target_section.map_or(0, |(_, address)| address))
+        }
+        // S + A
+        _ => value,
+    })
+}
+
 pub(crate) fn add_relocations(
     relocations: &mut RelocationMap,
     file: &object::File<'_>,
     section: &object::Section<'_, '_>,
 ) -> Result<()> {
-    for (offset64, mut relocation) in section.relocations() {
+    for (offset64, relocation) in section.relocations() {
         let offset = offset64 as usize;
         if offset as u64 != offset64 {
             continue;
         }
 
-        let offset = offset as usize;
-        if matches!(relocation.kind(), RelocationKind::Absolute) {
-            if let RelocationTarget::Symbol(symbol_idx) = relocation.target() {
-                match file.symbol_by_index(symbol_idx) {
-                    Ok(symbol) => {
-                        let addend = symbol.address().wrapping_add(relocation.addend() as u64);
-                        relocation.set_addend(addend as i64);
-                    }
-                    Err(_) => {
-                        return Err(Error::RelocationWithInvalidSymbol(
-                            section
-                                .name()
-                                .map_err(|e| Error::NamelessSection(e, offset))?
-                                .to_string(),
-                            offset,
-                        ));
-                    }
-                }
+        let section_name = || -> Result<String> {
+            Ok(section.name().map_err(|e| Error::NamelessSection(e, offset))?.to_string())
+        };
+
+        let (operation, size) = match relocation_operation(file, &relocation) {
+            Some(operation) => operation,
+            None => return Err(Error::UnsupportedRelocation(section_name()?, offset)),
+        };
+        if operation == RelocationOperation::Ignore {
+            continue;
+        }
+        let value = match relocated_value(file, section, offset, &relocation) {
+            Ok(value) => value,
+            Err(InvalidRelocation::InvalidTarget) => {
+                return Err(Error::RelocationWithInvalidSymbol(section_name()?, offset));
+            }
+            Err(InvalidRelocation::CrossSectionRelative) => {
+                return Err(Error::UnsupportedRelocation(section_name()?, offset));
             }
+        };
 
-            if relocations.insert(offset, relocation).is_some() {
-                return Err(Error::MultipleRelocations(
-                    section.name().map_err(|e| Error::NamelessSection(e, offset))?.to_string(),
-                    offset,
-                ));
+        // Add and subtract relocations are combined with any other relocations of the same value,
+        // other relocations must be the only relocation of their value.
+        match (relocations.0.get_mut(&offset), operation) {
+            (Some(existing), RelocationOperation::Add) => {
+                existing.addend = existing.addend.wrapping_add(value);
+            }
+            (Some(existing), RelocationOperation::Sub) => {
+                existing.addend = existing.addend.wrapping_sub(value);
+            }
+            (Some(_), RelocationOperation::Set) => {
+                return Err(Error::MultipleRelocations(section_name()?, offset));
+            }
+            (None, RelocationOperation::Set) => {
+                let implicit_addend = relocation.has_implicit_addend();
+                relocations.0.insert(offset, Relocation { implicit_addend, addend: value, size });
+            }
+            (None, RelocationOperation::Add) => {
+                relocations
+                    .0
+                    .insert(offset, Relocation { implicit_addend: true, addend: value, size });
+            }
+            (None, RelocationOperation::Sub) => {
+                let addend = 0u64.wrapping_sub(value);
+                relocations.0.insert(offset, Relocation { implicit_addend: true, addend, size });
             }
-        } else {
-            return Err(Error::UnsupportedRelocation(
-                section.name().map_err(|e| Error::NamelessSection(e, offset))?.to_string(),
-                offset,
-            ));
+            (_, RelocationOperation::Ignore) => unreachable!("ignored relocation wasn't skipped"),
         }
     }
 
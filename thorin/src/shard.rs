@@ -0,0 +1,68 @@
+use std::{io, num::NonZeroUsize};
+
+use crate::package::DwoId;
+
+/// How the units of a sharded DWARF package are partitioned into shards, see
+/// `DwarfPackage::with_sharding`.
+///
+/// Type units are added to the shards of the compilation units which reference them, so a type
+/// unit can be in more than one shard.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Sharding {
+    /// Partition compilation units into this many shards by their `DwoId`, so that a compilation
+    /// unit is always in the same shard of packages with the same number of shards.
+    ByDwoId(NonZeroUsize),
+    /// Add compilation units to a shard until adding another would make the shard larger than
+    /// this many bytes, then start a new shard. Sizes of units are estimated from the size of
+    /// their contributions in the input (excluding strings in DWARF packages, and index sections),
+    /// so shards can be slightly larger than the budget, and a compilation unit which is larger
+    /// than the budget is in a shard of its own.
+    BySize(u64),
+}
+
+/// Shard of a sharded DWARF package containing each compilation unit, returned by
+/// `DwarfPackage::finish_shards` and `DwarfPackage::finish_shards_to`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ShardManifest {
+    shard_count: usize,
+    /// `DwoId` of each compilation unit and the index of its shard, sorted by `DwoId`.
+    compilation_units: Vec<(DwoId, usize)>,
+}
+
+impl ShardManifest {
+    /// Create a manifest for `shard_count` shards from the `DwoId` of each compilation unit and
+    /// the index of its shard.
+    pub(crate) fn new(shard_count: usize, mut compilation_units: Vec<(DwoId, usize)>) -> Self {
+        compilation_units.sort_by_key(|(dwo_id, shard)| (dwo_id.0, *shard));
+        Self { shard_count, compilation_units }
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shard_count
+    }
+
+    /// Returns the index of the shard containing the compilation unit with `DwoId` `dwo_id`.
+    pub fn shard_of(&self, dwo_id: DwoId) -> Option<usize> {
+        self.compilation_units
+            .binary_search_by_key(&dwo_id.0, |(dwo_id, _)| dwo_id.0)
+            .ok()
+            .map(|index| self.compilation_units[index].1)
+    }
+
+    /// Returns the `DwoId` of each compilation unit and the index of its shard, in order of
+    /// `DwoId`.
+    pub fn compilation_units(&self) -> impl Iterator<Item = (DwoId, usize)> + '_ {
+        self.compilation_units.iter().copied()
+    }
+
+    /// Write the manifest to `output` as text: a `shards <count>` line, followed by a line with
+    /// the `DwoId` (in hexadecimal) and shard index of each compilation unit.
+    pub fn write<W: io::Write>(&self, mut output: W) -> io::Result<()> {
+        writeln!(output, "shards {}", self.shard_count)?;
+        for (dwo_id, shard) in &self.compilation_units {
+            writeln!(output, "0x{:016x} {}", dwo_id.0, shard)?;
+        }
+        Ok(())
+    }
+}
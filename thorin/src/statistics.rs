@@ -0,0 +1,24 @@
+/// Statistics about a created DWARF package, returned by `DwarfPackage::finish_with_statistics`
+/// and `DwarfPackage::finish_to_with_statistics`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct PackageStatistics {
+    /// Number of input objects added to the DWARF package, counting each archive member and
+    /// each DWARF object referenced by an executable.
+    pub input_objects: usize,
+    /// Number of compilation units in the DWARF package.
+    pub compilation_units: usize,
+    /// Number of type units in the DWARF package.
+    pub type_units: usize,
+    /// Number of type units which were skipped because a type unit with the same signature was
+    /// already in the DWARF package.
+    pub duplicate_type_units: usize,
+    /// Size in bytes of the type units which were skipped as duplicates.
+    pub duplicate_type_unit_bytes: u64,
+    /// Size in bytes of the strings (including their null terminators) referenced by input
+    /// objects which were already in the DWARF package's `.debug_str.dwo` section.
+    pub duplicate_string_bytes: u64,
+    /// Name and size in bytes of each section of the DWARF package, in the order the sections
+    /// were created. Sizes are of the uncompressed contents of sections.
+    pub section_sizes: Vec<(String, u64)>,
+}
@@ -0,0 +1,362 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use object::{
+    elf,
+    write::{
+        elf::{FileHeader, SectionHeader, Writer},
+        Object as WritableObject, StreamingBuffer,
+    },
+    AddressSize, Architecture, BinaryFormat, Endianness, SectionFlags, SectionKind,
+};
+use tracing::debug;
+
+use crate::{
+    compress::{compress_section, compressed_section_align, OutputCompression},
+    error::{Error, Result},
+};
+
+/// Counter used to give the temporary files of each `StreamingObject` in a process unique names.
+static STREAMING_OBJECT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Size of the buffer used when copying section data from temporary files to the output.
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Identifier for a section of a `StreamingObject`.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub(crate) struct StreamingSectionId(usize);
+
+/// Section of a `StreamingObject`, contents are written to a temporary file as they are appended.
+struct StreamingSection {
+    /// Name of the section.
+    name: Vec<u8>,
+    /// Path to the temporary file containing the section's contents.
+    path: PathBuf,
+    /// Temporary file containing the section's contents, `None` once the section is dropped.
+    file: Option<BufWriter<File>>,
+    /// Size of the section's contents.
+    size: u64,
+    /// Alignment of the section.
+    align: u64,
+    /// `sh_type` of the section.
+    sh_type: u32,
+    /// `sh_flags` of the section.
+    flags: u64,
+}
+
+impl StreamingSection {
+    /// Returns the temporary file of the section.
+    fn file(&mut self) -> &mut BufWriter<File> {
+        self.file.as_mut().expect("temporary file of section used after drop")
+    }
+}
+
+impl Drop for StreamingSection {
+    fn drop(&mut self) {
+        // Close the temporary file before removing it, removing an open file fails on some
+        // platforms. Failing to remove a temporary file isn't worth reporting.
+        drop(self.file.take());
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// ELF object file being created, where the contents of sections are written to temporary files
+/// as they are appended rather than kept in memory.
+///
+/// Once all sections have been appended, `write` writes the object file to the output, copying
+/// the contents of each section from its temporary file. Output is identical to the output of
+/// emitting an `object::write::Object` with the same sections, which `into_object` creates.
+pub(crate) struct StreamingObject {
+    /// Directory that the temporary files are created in.
+    dir: PathBuf,
+    /// Prefix of the names of temporary files, unique to this object.
+    prefix: String,
+    /// Architecture of the object file being created.
+    architecture: Architecture,
+    /// Endianness of the object file being created.
+    endianness: Endianness,
+    /// Is the object file being created a 64-bit object file?
+    is_64: bool,
+    /// ELF machine of the object file being created.
+    e_machine: u16,
+    /// Sections of the object file, in order of creation.
+    sections: Vec<StreamingSection>,
+}
+
+impl StreamingObject {
+    /// Create a new `StreamingObject` which creates temporary files in `dir`.
+    ///
+    /// Returns an `Error::UnsupportedStreamingArchitecture` if an ELF object file can't be written
+    /// for `architecture`, before any sections are appended.
+    pub(crate) fn new(
+        dir: &Path,
+        architecture: Architecture,
+        endianness: Endianness,
+    ) -> Result<Self> {
+        let is_64 = match architecture.address_size() {
+            Some(AddressSize::U64) => true,
+            Some(_) => false,
+            None => return Err(Error::UnsupportedStreamingArchitecture(architecture)),
+        };
+        let e_machine = elf_machine(architecture)?;
+
+        let count = STREAMING_OBJECT_COUNT.fetch_add(1, Ordering::Relaxed);
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            prefix: format!("thorin-{}-{}", std::process::id(), count),
+            architecture,
+            endianness,
+            is_64,
+            e_machine,
+            sections: Vec::new(),
+        })
+    }
+
+    /// Add a new section with type `sh_type` to the object, creating its temporary file.
+    pub(crate) fn add_section(
+        &mut self,
+        name: Vec<u8>,
+        sh_type: u32,
+    ) -> Result<StreamingSectionId> {
+        let id = StreamingSectionId(self.sections.len());
+        let path = self.dir.join(format!("{}{}", self.prefix, String::from_utf8_lossy(&name)));
+        debug!(?path, "creating temporary file for section");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(Error::CreateTemporaryFile)?;
+        self.sections.push(StreamingSection {
+            name,
+            path,
+            file: Some(BufWriter::new(file)),
+            size: 0,
+            align: 1,
+            sh_type,
+            flags: 0,
+        });
+        Ok(id)
+    }
+
+    /// Append data to a section, padding the section with zeros so that the data is aligned to
+    /// `align`, returning the offset of the data in the section.
+    pub(crate) fn append_section_data(
+        &mut self,
+        id: StreamingSectionId,
+        data: &[u8],
+        align: u64,
+    ) -> Result<u64> {
+        let section = &mut self.sections[id.0];
+        let offset = (section.size + align - 1) & !(align - 1);
+        let padding = (offset - section.size) as usize;
+        section.align = section.align.max(align);
+
+        let file = section.file();
+        file.write_all(&vec![0; padding]).map_err(Error::WriteTemporaryFile)?;
+        file.write_all(data).map_err(Error::WriteTemporaryFile)?;
+
+        section.size = offset + data.len() as u64;
+        Ok(offset)
+    }
+
+    /// Compress the contents of a section with `compression`, replacing its temporary file with
+    /// a temporary file containing the contents of the `SHF_COMPRESSED` section.
+    pub(crate) fn compress_section(
+        &mut self,
+        id: StreamingSectionId,
+        compression: OutputCompression,
+        is_64: bool,
+    ) -> Result<()> {
+        let section = &mut self.sections[id.0];
+        let path = self.dir.join(format!(
+            "{}{}.compressed",
+            self.prefix,
+            String::from_utf8_lossy(&section.name)
+        ));
+        debug!(?path, "creating temporary file for compressed section");
+
+        let compressed = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(Error::CreateTemporaryFile)?;
+
+        let (size, align) = (section.size, section.align);
+        let file = section.file();
+        file.flush().map_err(Error::WriteTemporaryFile)?;
+        let file = file.get_mut();
+        file.seek(SeekFrom::Start(0)).map_err(Error::ReadTemporaryFile)?;
+        let mut compressed = compress_section(
+            compression,
+            self.endianness,
+            is_64,
+            size,
+            align,
+            BufReader::new(file),
+            BufWriter::new(compressed),
+        )
+        .map_err(Error::CompressSection)?;
+        let size = compressed.seek(SeekFrom::End(0)).map_err(Error::WriteTemporaryFile)?;
+
+        // Replacing the section drops the uncompressed section, removing its temporary file.
+        *section = StreamingSection {
+            name: std::mem::take(&mut section.name),
+            path,
+            file: Some(compressed),
+            size,
+            align: compressed_section_align(is_64),
+            sh_type: section.sh_type,
+            flags: elf::SHF_COMPRESSED.into(),
+        };
+        Ok(())
+    }
+
+    /// Write the object file to `output`, copying the contents of each section from its
+    /// temporary file.
+    #[tracing::instrument(level = "trace", skip(self, output))]
+    pub(crate) fn write<W: io::Write>(mut self, output: W) -> Result<()> {
+        // Section names need to outlive the writer, which is used while section contents are
+        // being copied.
+        let names: Vec<_> =
+            self.sections.iter_mut().map(|section| std::mem::take(&mut section.name)).collect();
+
+        let mut buffer = StreamingBuffer::new(output);
+        let mut writer = Writer::new(self.endianness, self.is_64, &mut buffer);
+
+        // Reserve space for everything in the same order as `object::write::Object` so that the
+        // output is identical.
+        writer.reserve_file_header();
+        let mut section_offsets = Vec::with_capacity(self.sections.len());
+        for (section, name) in self.sections.iter().zip(&names) {
+            writer.reserve_section_index();
+            let size = section
+                .size
+                .try_into()
+                .map_err(|_| Error::StreamingSectionTooLarge(section.size))?;
+            let offset = writer.reserve(size, section.align as usize);
+            let str_id = writer.add_section_name(name);
+            section_offsets.push((offset, str_id));
+        }
+        writer.reserve_symtab_section_index();
+        writer.reserve_symtab();
+        writer.reserve_symtab_shndx();
+        writer.reserve_strtab_section_index();
+        writer.reserve_strtab();
+        writer.reserve_shstrtab_section_index();
+        writer.reserve_shstrtab();
+        writer.reserve_section_headers();
+
+        writer.write_file_header(&FileHeader {
+            os_abi: elf::ELFOSABI_NONE,
+            abi_version: 0,
+            e_type: elf::ET_REL,
+            e_machine: self.e_machine,
+            e_entry: 0,
+            e_flags: 0,
+        })?;
+
+        let mut copy_buffer = vec![0; COPY_BUFFER_SIZE];
+        for section in &mut self.sections {
+            if section.size == 0 {
+                continue;
+            }
+
+            writer.write_align(section.align as usize);
+            let file = section.file();
+            file.flush().map_err(Error::WriteTemporaryFile)?;
+            let file = file.get_mut();
+            file.seek(SeekFrom::Start(0)).map_err(Error::ReadTemporaryFile)?;
+            loop {
+                let len = file.read(&mut copy_buffer).map_err(Error::ReadTemporaryFile)?;
+                if len == 0 {
+                    break;
+                }
+                writer.write(&copy_buffer[..len]);
+            }
+        }
+
+        writer.write_null_symbol();
+        writer.write_symtab_shndx();
+        writer.write_strtab();
+        writer.write_shstrtab();
+
+        writer.write_null_section_header();
+        for (section, (offset, str_id)) in self.sections.iter().zip(section_offsets) {
+            writer.write_section_header(&SectionHeader {
+                name: Some(str_id),
+                sh_type: section.sh_type,
+                sh_flags: section.flags,
+                sh_addr: 0,
+                sh_offset: offset as u64,
+                sh_size: section.size,
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: section.align,
+                sh_entsize: 0,
+            });
+        }
+        let symtab_num_local = writer.symbol_count();
+        writer.write_symtab_section_header(symtab_num_local);
+        writer.write_symtab_shndx_section_header();
+        writer.write_strtab_section_header();
+        writer.write_shstrtab_section_header();
+
+        buffer.result().map_err(Error::WriteOutput)
+    }
+
+    /// Returns an `object::write::Object` with the same sections as this object, reading the
+    /// contents of each section from its temporary file into memory.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub(crate) fn into_object<'file>(mut self) -> Result<WritableObject<'file>> {
+        let mut obj = WritableObject::new(BinaryFormat::Elf, self.architecture, self.endianness);
+        for section in &mut self.sections {
+            let kind = match section.sh_type {
+                elf::SHT_NOTE => SectionKind::Note,
+                _ => SectionKind::Debug,
+            };
+            let id = obj.add_section(Vec::new(), std::mem::take(&mut section.name), kind);
+
+            let mut data = Vec::new();
+            let file = section.file();
+            file.flush().map_err(Error::WriteTemporaryFile)?;
+            let file = file.get_mut();
+            file.seek(SeekFrom::Start(0)).map_err(Error::ReadTemporaryFile)?;
+            file.read_to_end(&mut data).map_err(Error::ReadTemporaryFile)?;
+
+            let output_section = obj.section_mut(id);
+            output_section.set_data(data, section.align);
+            if section.flags != 0 {
+                output_section.flags = SectionFlags::Elf { sh_flags: section.flags };
+            }
+        }
+        Ok(obj)
+    }
+}
+
+/// Returns the ELF machine of `architecture`.
+fn elf_machine(architecture: Architecture) -> Result<u16> {
+    Ok(match architecture {
+        Architecture::Aarch64 => elf::EM_AARCH64,
+        Architecture::Arm => elf::EM_ARM,
+        Architecture::Avr => elf::EM_AVR,
+        Architecture::Bpf => elf::EM_BPF,
+        Architecture::I386 => elf::EM_386,
+        Architecture::X86_64 | Architecture::X86_64_X32 => elf::EM_X86_64,
+        Architecture::Hexagon => elf::EM_HEXAGON,
+        Architecture::Mips | Architecture::Mips64 => elf::EM_MIPS,
+        Architecture::Msp430 => elf::EM_MSP430,
+        Architecture::PowerPc => elf::EM_PPC,
+        Architecture::PowerPc64 => elf::EM_PPC64,
+        Architecture::Riscv32 | Architecture::Riscv64 => elf::EM_RISCV,
+        Architecture::S390x => elf::EM_S390,
+        Architecture::Sparc64 => elf::EM_SPARCV9,
+        _ => return Err(Error::UnsupportedStreamingArchitecture(architecture)),
+    })
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
 
 use gimli::{
     write::{EndianVec, Writer},
@@ -33,12 +33,19 @@ pub(crate) struct PackageStringTable<E: gimli::Endianity> {
     data: EndianVec<E>,
     strings: IndexSet<Vec<u8>>,
     offsets: HashMap<PackageStringId, PackageStringOffset>,
+    /// Size of the strings (including null terminators) inserted which were already in the table.
+    duplicate_bytes: u64,
 }
 
 impl<E: gimli::Endianity> PackageStringTable<E> {
     /// Create a new `PackageStringTable` with a given endianity.
     pub(crate) fn new(endianness: E) -> Self {
-        Self { data: EndianVec::new(endianness), strings: IndexSet::new(), offsets: HashMap::new() }
+        Self {
+            data: EndianVec::new(endianness),
+            strings: IndexSet::new(),
+            offsets: HashMap::new(),
+            duplicate_bytes: 0,
+        }
     }
 
     /// Insert a string into the string table and return its offset in the table. If the string is
@@ -52,6 +59,7 @@ impl<E: gimli::Endianity> PackageStringTable<E> {
         let (index, is_new) = self.strings.insert_full(bytes.clone());
         let index = PackageStringId(index);
         if !is_new {
+            self.duplicate_bytes += bytes.len() as u64 + 1;
             return Ok(*self.offsets.get(&index).expect("insert exists but no offset"));
         }
 
@@ -67,44 +75,39 @@ impl<E: gimli::Endianity> PackageStringTable<E> {
         Ok(offset)
     }
 
-    /// Adds strings from input `.debug_str_offsets` and `.debug_str` into the string table, returns
-    /// data for a equivalent `.debug_str_offsets` section with offsets pointing into the new
-    /// `.debug_str` section.
+    /// Adds strings referenced by an input `.debug_str_offsets` section (see
+    /// `read_str_offsets_section`) into the string table, returns data for a equivalent
+    /// `.debug_str_offsets` section with offsets pointing into the new `.debug_str` section.
     pub(crate) fn remap_str_offsets_section(
         &mut self,
-        debug_str: gimli::DebugStr<EndianSlice<E>>,
-        debug_str_offsets: gimli::DebugStrOffsets<EndianSlice<E>>,
+        debug_str: &[u8],
+        strings: &[Range<usize>],
         section_size: u64,
         endian: E,
         encoding: Encoding,
     ) -> Result<EndianVec<E>> {
-        let entry_size = match encoding.format {
-            Format::Dwarf32 => 4,
-            Format::Dwarf64 => 8,
-        };
-
         let mut data = EndianVec::new(endian);
 
-        // `DebugStrOffsetsBase` knows to skip past the header with DWARF 5.
-        let base: gimli::DebugStrOffsetsBase<usize> =
-            DebugStrOffsetsBase::default_for_encoding_and_file(encoding, DwarfFileType::Dwo);
-
         if encoding.is_std_dwarf_package_format() {
             match encoding.format {
                 Format::Dwarf32 => {
                     // Unit length (4 bytes): size of the offsets section without this
                     // header (8 bytes total).
-                    data.write_u32(
-                        (section_size - 8)
-                            .try_into()
-                            .expect("section size w/out header larger than u32"),
-                    )?;
+                    let unit_length = section_size
+                        .checked_sub(8)
+                        .ok_or(Error::StrOffsetsSectionTooSmall(section_size))?
+                        .try_into()
+                        .map_err(|_| Error::StrOffsetsSectionTooLarge(section_size))?;
+                    data.write_u32(unit_length)?;
                 }
                 Format::Dwarf64 => {
                     // Unit length (4 bytes then 8 bytes): size of the offsets section without
                     // this header (16 bytes total).
                     data.write_u32(u32::MAX)?;
-                    data.write_u64(section_size - 16)?;
+                    let unit_length = section_size
+                        .checked_sub(16)
+                        .ok_or(Error::StrOffsetsSectionTooSmall(section_size))?;
+                    data.write_u64(unit_length)?;
                 }
             };
             // Version (2 bytes): DWARF 5
@@ -112,27 +115,19 @@ impl<E: gimli::Endianity> PackageStringTable<E> {
             // Reserved padding (2 bytes)
             data.write_u16(0)?;
         }
-        debug!(?base);
-
-        let base_offset: u64 = base.0.try_into().expect("base offset larger than u64");
-        let num_elements = (section_size - base_offset) / entry_size;
-        debug!(?section_size, ?base_offset, ?num_elements);
-
-        for i in 0..num_elements {
-            let dwo_index = DebugStrOffsetsIndex(i as usize);
-            let dwo_offset = debug_str_offsets
-                .get_str_offset(encoding.format, base, dwo_index)
-                .map_err(|e| Error::OffsetAtIndex(e, i))?;
-            let dwo_str =
-                debug_str.get_str(dwo_offset).map_err(|e| Error::StrAtOffset(e, dwo_offset.0))?;
-            let dwo_str = dwo_str.to_string()?;
 
-            let dwp_offset = self.get_or_insert(dwo_str)?;
+        for string in strings {
+            let dwp_offset = self.get_or_insert(&debug_str[string.clone()])?;
 
             match encoding.format {
                 Format::Dwarf32 => {
-                    let dwp_offset =
-                        dwp_offset.0.try_into().expect("string offset larger than u32");
+                    // Units can't be converted to DWARF64 (`DW_FORM_strx` is an index into a
+                    // table of offsets of the unit's size), so fail if the merged string table
+                    // grows too large.
+                    let dwp_offset = dwp_offset
+                        .0
+                        .try_into()
+                        .map_err(|_| Error::StrOffsetTooLarge(dwp_offset.0 as u64))?;
                     data.write_u32(dwp_offset)?;
                 }
                 Format::Dwarf64 => {
@@ -146,8 +141,83 @@ impl<E: gimli::Endianity> PackageStringTable<E> {
         Ok(data)
     }
 
+    /// Returns the size of the strings (including null terminators) which were inserted into the
+    /// table when they were already in the table.
+    pub(crate) fn duplicate_bytes(&self) -> u64 {
+        self.duplicate_bytes
+    }
+
+    /// Returns the size of the accumulated `.debug_str` section data.
+    pub(crate) fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
     /// Returns the accumulated `.debug_str` section data
     pub(crate) fn finish(self) -> EndianVec<E> {
         self.data
     }
 }
+
+/// Returns the ranges of the strings referenced by the contribution `contribution` to an input
+/// `.debug_str_offsets` section, given the ranges of the strings referenced by the whole section
+/// (see `read_str_offsets_section`), or `None` if the contribution is out of bounds.
+pub(crate) fn str_offsets_contribution_strings(
+    strings: &[Range<usize>],
+    contribution: Range<usize>,
+    encoding: Encoding,
+) -> Option<&[Range<usize>]> {
+    let entry_size = usize::from(encoding.format.word_size());
+    let base: gimli::DebugStrOffsetsBase<usize> =
+        DebugStrOffsetsBase::default_for_encoding_and_file(encoding, DwarfFileType::Dwo);
+    // Offsets are read as if the section had a single header, so the offsets of a contribution
+    // (after its own header) start at the same index as the contribution would without a header.
+    let end = contribution.end.checked_sub(base.0)? / entry_size;
+    strings.get(contribution.start / entry_size..end)
+}
+
+/// Returns the range of each string in `.debug_str` referenced by an input `.debug_str_offsets`
+/// section, in the order of the offsets.
+///
+/// Reading the strings doesn't require the string table, so this can be done before the input is
+/// added to the package (and in parallel with other inputs).
+pub(crate) fn read_str_offsets_section<E: gimli::Endianity>(
+    debug_str: gimli::DebugStr<EndianSlice<E>>,
+    debug_str_offsets: gimli::DebugStrOffsets<EndianSlice<E>>,
+    section_size: u64,
+    encoding: Encoding,
+) -> Result<Vec<Range<usize>>> {
+    let entry_size = match encoding.format {
+        Format::Dwarf32 => 4,
+        Format::Dwarf64 => 8,
+    };
+
+    // `DebugStrOffsetsBase` knows to skip past the header with DWARF 5.
+    let base: gimli::DebugStrOffsetsBase<usize> =
+        DebugStrOffsetsBase::default_for_encoding_and_file(encoding, DwarfFileType::Dwo);
+    debug!(?base);
+
+    let base_offset: u64 = base.0.try_into().expect("base offset larger than u64");
+    let num_elements = section_size.checked_sub(base_offset).ok_or_else(|| {
+        Error::StrOffsetsSectionTooSmall(section_size).in_section(".debug_str_offsets.dwo", None)
+    })? / entry_size;
+    debug!(?section_size, ?base_offset, ?num_elements);
+
+    let mut strings = Vec::new();
+    for i in 0..num_elements {
+        let dwo_index = DebugStrOffsetsIndex(i as usize);
+        let dwo_offset =
+            debug_str_offsets.get_str_offset(encoding.format, base, dwo_index).map_err(|e| {
+                let offset = base_offset + i * entry_size;
+                Error::OffsetAtIndex(e, i).in_section(".debug_str_offsets.dwo", Some(offset))
+            })?;
+        let in_debug_str = |e: Error| e.in_section(".debug_str.dwo", Some(dwo_offset.0 as u64));
+        let dwo_str = debug_str
+            .get_str(dwo_offset)
+            .map_err(|e| in_debug_str(Error::StrAtOffset(e, dwo_offset.0)))?;
+        // Check that the string is valid UTF-8.
+        dwo_str.to_string().map_err(|e| in_debug_str(e.into()))?;
+        strings.push(dwo_offset.0..dwo_offset.0 + dwo_str.len());
+    }
+
+    Ok(strings)
+}
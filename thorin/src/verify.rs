@@ -0,0 +1,227 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use tracing::debug;
+
+use crate::{
+    error::{Error, Result},
+    executable::{referenced_units, ReferencedUnit},
+    index::Bucketable,
+    package::{DwarfObject, DwoId},
+    reader::{DwarfPackageReader, UnitContribution, UnitDescription},
+    relocate::RelocationMap,
+    Session,
+};
+
+/// Problem found by `DwarfPackageReader::verify` when cross-checking a DWARF package against the
+/// executables which reference it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum VerificationProblem {
+    /// Executable references a split unit which isn't in the DWARF package.
+    MissingUnit(ReferencedUnit),
+    /// Contribution of a unit in the index of the DWARF package isn't within its section.
+    ContributionOutOfBounds { id: DwarfObject, contribution: UnitContribution },
+    /// Unit in the DWARF package referenced by an executable couldn't be read.
+    UnreadableUnit { id: DwarfObject, error: Error },
+    /// Unit in the DWARF package has a different identifier than its row in the index.
+    IdMismatch { id: DwarfObject, found: Option<DwarfObject> },
+    /// `DW_AT_dwo_name` of a skeleton unit in an executable is different from the
+    /// `DW_AT_dwo_name` of the split unit in the DWARF package.
+    DwoNameMismatch { executable: PathBuf, id: DwoId, skeleton: String, split: String },
+    /// `DW_AT_comp_dir` of a skeleton unit in an executable is different from the
+    /// `DW_AT_comp_dir` of the split unit in the DWARF package.
+    CompDirMismatch { executable: PathBuf, id: DwoId, skeleton: String, split: String },
+}
+
+impl fmt::Display for VerificationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerificationProblem::MissingUnit(unit) => write!(
+                f,
+                "Unit 0x{:016x} (from `{}`) referenced by `{}` is missing from the DWARF package",
+                unit.id.index(),
+                unit.dwo_name,
+                unit.executable.display()
+            ),
+            VerificationProblem::ContributionOutOfBounds { id, contribution } => write!(
+                f,
+                "Contribution of unit 0x{:016x} to `{}` (offset 0x{:x}, size 0x{:x}) is out of \
+                 bounds",
+                id.index(),
+                contribution.section_name(),
+                contribution.offset,
+                contribution.size
+            ),
+            VerificationProblem::UnreadableUnit { id, error } => {
+                write!(f, "Failed to read unit 0x{:016x}: {}", id.index(), error)?;
+                let mut source = std::error::Error::source(error);
+                while let Some(error) = source {
+                    write!(f, ": {}", error)?;
+                    source = error.source();
+                }
+                Ok(())
+            }
+            VerificationProblem::IdMismatch { id, found: Some(found) } => write!(
+                f,
+                "Unit 0x{:016x} in the index has identifier 0x{:016x}",
+                id.index(),
+                found.index()
+            ),
+            VerificationProblem::IdMismatch { id, found: None } => {
+                write!(f, "Unit 0x{:016x} in the index has no identifier", id.index())
+            }
+            VerificationProblem::DwoNameMismatch { executable, id, skeleton, split } => write!(
+                f,
+                "Unit 0x{:016x} has DWARF object name `{}` in `{}` but `{}` in the DWARF package",
+                id.0,
+                skeleton,
+                executable.display(),
+                split
+            ),
+            VerificationProblem::CompDirMismatch { executable, id, skeleton, split } => write!(
+                f,
+                "Unit 0x{:016x} has compilation directory `{}` in `{}` but `{}` in the DWARF \
+                 package",
+                id.0,
+                skeleton,
+                executable.display(),
+                split
+            ),
+        }
+    }
+}
+
+impl<'input> DwarfPackageReader<'input> {
+    /// Cross-check the DWARF package against `executables`, returning every problem found.
+    ///
+    /// Checks that the contributions of every unit in the index are within their sections, that
+    /// every split unit referenced by a skeleton unit in the executables is in the DWARF package,
+    /// and that the `DW_AT_dwo_name` and `DW_AT_comp_dir` of the split units are consistent with
+    /// their skeleton units. An `Err` is only returned if an executable can't be read.
+    ///
+    /// Skeleton units of stripped executables are read from their separate debug files in
+    /// `/usr/lib/debug`, see `verify_with_debug_dirs`.
+    #[tracing::instrument(level = "trace", skip(self, sess, executables))]
+    pub fn verify<Sess, P>(
+        &self,
+        sess: &Sess,
+        executables: &[P],
+    ) -> Result<Vec<VerificationProblem>>
+    where
+        Sess: Session<RelocationMap>,
+        P: AsRef<Path>,
+    {
+        self.verify_with_debug_dirs(sess, executables, &[])
+    }
+
+    /// Cross-check the DWARF package against `executables` (see `verify`), reading the skeleton
+    /// units of stripped executables from their separate debug files in `debug_dirs` (see
+    /// `DwarfPackage::with_debug_dir`).
+    #[tracing::instrument(level = "trace", skip(self, sess, executables))]
+    pub fn verify_with_debug_dirs<Sess, P>(
+        &self,
+        sess: &Sess,
+        executables: &[P],
+        debug_dirs: &[PathBuf],
+    ) -> Result<Vec<VerificationProblem>>
+    where
+        Sess: Session<RelocationMap>,
+        P: AsRef<Path>,
+    {
+        let mut problems = Vec::new();
+        for unit in self.units() {
+            for contribution in unit.contributions() {
+                if self.contribution_data(contribution).is_err() {
+                    problems.push(VerificationProblem::ContributionOutOfBounds {
+                        id: unit.id(),
+                        contribution: *contribution,
+                    });
+                }
+            }
+        }
+
+        // Units can be referenced by more than one executable, only describe (and report problems
+        // reading) each unit once.
+        let mut descriptions: HashMap<DwarfObject, Option<UnitDescription>> = HashMap::new();
+        for executable in executables {
+            let executable = executable.as_ref();
+            for referenced in referenced_units(sess, executable, debug_dirs)
+                .map_err(|e| e.in_input(executable))?
+                .units
+            {
+                // There are no skeleton type units, see `DwarfPackage::add_executable`.
+                let id = match referenced.id {
+                    DwarfObject::Compilation(id) => id,
+                    DwarfObject::Type(_) => continue,
+                };
+
+                let unit = match self.unit(referenced.id) {
+                    Some(unit) => unit,
+                    None => {
+                        debug!(?id, "missing unit");
+                        problems.push(VerificationProblem::MissingUnit(referenced));
+                        continue;
+                    }
+                };
+
+                let description = descriptions.entry(referenced.id).or_insert_with(|| {
+                    match self.describe_unit(unit) {
+                        Ok(description) => {
+                            if description.id != Some(referenced.id) {
+                                problems.push(VerificationProblem::IdMismatch {
+                                    id: referenced.id,
+                                    found: description.id,
+                                });
+                            }
+                            Some(description)
+                        }
+                        // Out-of-bounds contributions have already been reported.
+                        Err(Error::InvalidContribution(..)) => None,
+                        Err(error) => {
+                            problems.push(VerificationProblem::UnreadableUnit {
+                                id: referenced.id,
+                                error,
+                            });
+                            None
+                        }
+                    }
+                });
+                let description = match description {
+                    Some(description) => description,
+                    None => continue,
+                };
+
+                if let Some(split) = &description.dwo_name {
+                    if *split != referenced.dwo_name {
+                        problems.push(VerificationProblem::DwoNameMismatch {
+                            executable: executable.to_path_buf(),
+                            id,
+                            skeleton: referenced.dwo_name.clone(),
+                            split: split.clone(),
+                        });
+                    }
+                }
+
+                // Split units don't usually have a `DW_AT_comp_dir`, only check it if they do.
+                if let (Some(skeleton), Some(split)) = (&referenced.comp_dir, &description.comp_dir)
+                {
+                    if skeleton != split {
+                        problems.push(VerificationProblem::CompDirMismatch {
+                            executable: executable.to_path_buf(),
+                            id,
+                            skeleton: skeleton.clone(),
+                            split: split.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        debug!(problem_count = problems.len());
+        Ok(problems)
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::InputLocation,
+    executable::ReferencedUnit,
+    index::Bucketable,
+    package::{DebugTypeSignature, DwoId},
+};
+
+/// Condition which doesn't prevent the DWARF package from being created but which users may want
+/// to know about, passed to `Session::warn`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// Member of an archive input isn't an object file, so was skipped.
+    SkippedArchiveMember(InputLocation),
+    /// Input object doesn't have a `.debug_info.dwo` section, so nothing from it was added to the
+    /// DWARF package.
+    NoDwarfObject(InputLocation),
+    /// Type unit was already added to the DWARF package from an earlier input, so this copy of it
+    /// was skipped.
+    DuplicateTypeUnit(InputLocation, DebugTypeSignature),
+    /// DWARF object referenced by an executable couldn't be read from any of the paths it could
+    /// be at, so was skipped (see `MissingReferencedObjectBehaviour::Skip`).
+    MissingReferencedObject(ReferencedUnit),
+    /// Type unit which was skipped as a duplicate differs from the type unit with the same
+    /// signature which is in the DWARF package, which indicates a signature collision or a
+    /// violation of the one definition rule (only when comparing type units, see
+    /// `DwarfPackage::with_type_unit_comparison`). Locations are of the first differing
+    /// debugging information entry of the type unit in the DWARF package and of the skipped type
+    /// unit.
+    MismatchedTypeUnit(DebugTypeSignature, InputLocation, InputLocation),
+    /// Compilation unit with the same `DwoId` as a compilation unit from an earlier input was
+    /// found, and one of them was skipped (see `DuplicateUnitBehaviour`).
+    DuplicateCompilationUnit(InputLocation, DwoId),
+    /// Executable without a `.debug_info` section references a separate debug file (by its build
+    /// ID or `.gnu_debuglink`) which couldn't be found, so its skeleton units couldn't be read
+    /// (see `DwarfPackage::with_debug_dir`).
+    MissingSeparateDebugFile(PathBuf),
+    /// Executable (or object in an archive passed as an executable) has neither a `.debug_info`
+    /// section nor a build ID or `.gnu_debuglink` to find its separate debug file with, so no
+    /// skeleton units were read from it.
+    NoDebugInformation(InputLocation),
+}
+
+impl Warning {
+    /// Record that this warning occurred in the input at `path` (in archive member `member`),
+    /// unless it is already known to have occurred in an input.
+    pub(crate) fn in_input(mut self, path: &Path, member: Option<&str>) -> Self {
+        let location = match &mut self {
+            Warning::SkippedArchiveMember(location)
+            | Warning::NoDwarfObject(location)
+            | Warning::DuplicateTypeUnit(location, _)
+            | Warning::DuplicateCompilationUnit(location, _)
+            | Warning::NoDebugInformation(location) => location,
+            Warning::MissingReferencedObject(_)
+            | Warning::MismatchedTypeUnit(..)
+            | Warning::MissingSeparateDebugFile(_) => return self,
+        };
+        if location.path.as_os_str().is_empty() {
+            location.path = path.to_path_buf();
+        }
+        if let Some(member) = member {
+            location.member.get_or_insert_with(|| member.to_string());
+        }
+        self
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::SkippedArchiveMember(location) => {
+                write!(f, "Skipped {}, which isn't an object file", location)
+            }
+            Warning::NoDwarfObject(location) => {
+                write!(f, "Skipped {}, which doesn't have a `.debug_info.dwo` section", location)
+            }
+            Warning::DuplicateTypeUnit(location, signature) => write!(
+                f,
+                "Skipped type unit 0x{:016x} in {}, which is already in the DWARF package",
+                signature.index(),
+                location
+            ),
+            Warning::MissingReferencedObject(unit) => write!(
+                f,
+                "Skipped unit 0x{:016x} referenced by `{}`, `{}` could not be read",
+                unit.id.index(),
+                unit.executable.display(),
+                unit.path().display()
+            ),
+            Warning::MismatchedTypeUnit(signature, kept, duplicate) => write!(
+                f,
+                "Type unit 0x{:016x} in {} differs from the type unit in the DWARF package from {}",
+                signature.index(),
+                duplicate,
+                kept
+            ),
+            Warning::DuplicateCompilationUnit(location, dwo_id) => write!(
+                f,
+                "Found compilation unit 0x{:016x} in {}, which was already in the DWARF package",
+                dwo_id.index(),
+                location
+            ),
+            Warning::MissingSeparateDebugFile(executable) => write!(
+                f,
+                "Separate debug file of `{}` could not be found, so its skeleton units were not read",
+                executable.display()
+            ),
+            Warning::NoDebugInformation(location) => write!(
+                f,
+                "Skipped {}, which has no `.debug_info` section or link to a separate debug file",
+                location
+            ),
+        }
+    }
+}